@@ -7,13 +7,16 @@ use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
 use std::sync::Arc;
 use windows::core::Interface;
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
 use windows::Win32::Foundation::{CloseHandle, FALSE, TRUE};
 use windows::Win32::Media::Audio::{
     eCommunications, eConsole, eMultimedia, eRender, IAudioSessionControl2,
     IAudioSessionManager2, IMMDevice, IMMDeviceEnumerator, ISimpleAudioVolume,
     MMDeviceEnumerator,
 };
-use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ,
+};
 use windows::Win32::System::ProcessStatus::K32GetModuleFileNameExW;
 use windows::Win32::System::Threading::{
     OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_INFORMATION,
@@ -28,6 +31,8 @@ pub struct AudioSession {
     pub process_name: String,
     pub display_name: String,
     pub is_muted: bool,
+    /// Friendly name of the output device, such as "Headphones (USB Audio)"
+    pub device_name: String,
 }
 
 /// Lightweight audio session manager
@@ -139,6 +144,15 @@ impl Default for AudioManager {
     }
 }
 
+/// Gets the friendly name of an audio device
+fn get_device_name(device: &IMMDevice) -> Option<String> {
+    unsafe {
+        let store = device.OpenPropertyStore(STGM_READ).ok()?;
+        let name = store.GetValue(&PKEY_Device_FriendlyName).ok()?.to_string();
+        (!name.is_empty()).then_some(name)
+    }
+}
+
 /// Collects sessions for a specific audio device
 fn collect_sessions_for_device(
     device: &IMMDevice,
@@ -146,6 +160,7 @@ fn collect_sessions_for_device(
     seen_pids: &mut HashSet<u32>,
     result: &mut Vec<AudioSession>,
 ) -> windows::core::Result<()> {
+    let device_name = get_device_name(device).unwrap_or_default();
     unsafe {
         let session_manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
         let session_enumerator = session_manager.GetSessionEnumerator()?;
@@ -180,6 +195,7 @@ fn collect_sessions_for_device(
                                     process_name: process_name.clone(),
                                     display_name: display_name.clone(),
                                     is_muted,
+                                    device_name: device_name.clone(),
                                 });
                             }
 
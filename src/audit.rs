@@ -0,0 +1,406 @@
+//! Decision audit log module
+//! Records every mute/unmute the engine performs, with the reason and the
+//! focus change that triggered it, in a ring buffer and optionally a JSONL file.
+
+use crate::clock;
+use crate::events;
+use crate::muter::Decision;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of events kept in memory
+pub const DEFAULT_CAPACITY: usize = 500;
+
+/// The JSONL file is rotated to `<name>.old` once it grows past this size
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// What the engine did to a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Muted,
+    Unmuted,
+}
+
+/// Why the engine did it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonCode {
+    /// Muting was turned off (or the app is shutting down)
+    Disabled,
+    Snoozed,
+    Away,
+    AwayAllowed,
+    AlwaysMuted,
+    Excluded,
+    Exempt,
+    Pinned,
+    Foreground,
+    Visible,
+    CurrentWorkspace,
+    Peek,
+    Background,
+    /// The audio session disappeared while we had it muted
+    SessionGone,
+    /// The policy script decided
+    Script,
+}
+
+impl From<Decision> for ReasonCode {
+    fn from(decision: Decision) -> Self {
+        match decision {
+            // The engine never touches its own process, so this never gets recorded
+            Decision::OwnProcess | Decision::Disabled => ReasonCode::Disabled,
+            Decision::Snoozed => ReasonCode::Snoozed,
+            Decision::Away => ReasonCode::Away,
+            Decision::AwayAllowed => ReasonCode::AwayAllowed,
+            Decision::AlwaysMuted => ReasonCode::AlwaysMuted,
+            Decision::Excluded => ReasonCode::Excluded,
+            Decision::Exempt => ReasonCode::Exempt,
+            Decision::Pinned => ReasonCode::Pinned,
+            Decision::Foreground => ReasonCode::Foreground,
+            Decision::Visible => ReasonCode::Visible,
+            Decision::CurrentWorkspace => ReasonCode::CurrentWorkspace,
+            Decision::Peek => ReasonCode::Peek,
+            Decision::Background => ReasonCode::Background,
+            Decision::ScriptMute | Decision::ScriptUnmute | Decision::ScriptAllow => {
+                ReasonCode::Script
+            }
+        }
+    }
+}
+
+impl ReasonCode {
+    /// Gets the reason code as written to the log
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReasonCode::Disabled => "disabled",
+            ReasonCode::Snoozed => "snoozed",
+            ReasonCode::Away => "away",
+            ReasonCode::AwayAllowed => "away_allowed",
+            ReasonCode::AlwaysMuted => "always_muted",
+            ReasonCode::Excluded => "excluded",
+            ReasonCode::Exempt => "exempt",
+            ReasonCode::Pinned => "pinned",
+            ReasonCode::Foreground => "foreground",
+            ReasonCode::Visible => "visible",
+            ReasonCode::CurrentWorkspace => "current_workspace",
+            ReasonCode::Peek => "peek",
+            ReasonCode::Background => "background",
+            ReasonCode::SessionGone => "session_gone",
+            ReasonCode::Script => "script",
+        }
+    }
+}
+
+/// The foreground change that triggered an update
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FocusChange {
+    pub from_pid: Option<u32>,
+    pub to_pid: Option<u32>,
+    pub to_app: Option<String>,
+}
+
+/// A single mute or unmute performed by the engine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+    pub pid: u32,
+    pub app: String,
+    pub action: AuditAction,
+    pub reason: ReasonCode,
+    /// Set when the update was caused by a foreground change
+    pub focus_change: Option<FocusChange>,
+    /// The engine was in dry-run mode, so audio was not actually touched
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl AuditEvent {
+    /// Creates an event stamped with the current time
+    pub fn new(
+        pid: u32,
+        app: &str,
+        action: AuditAction,
+        reason: ReasonCode,
+        focus_change: Option<FocusChange>,
+    ) -> Self {
+        Self {
+            timestamp_ms: unix_millis(),
+            pid,
+            app: app.to_string(),
+            action,
+            reason,
+            focus_change,
+            dry_run: false,
+        }
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offset = clock::local_utc_offset_secs();
+        let time = clock::format_local_time(self.timestamp_ms / 1000, offset);
+        let action = match self.action {
+            AuditAction::Muted => "muted",
+            AuditAction::Unmuted => "unmuted",
+        };
+        write!(f, "{} {} {} ({}): {}", time, action, self.app, self.pid, self.reason.as_str())?;
+
+        if let Some(change) = &self.focus_change {
+            let pid = |pid: Option<u32>| pid.map(|p| p.to_string()).unwrap_or_else(|| "-".into());
+            write!(f, ", focus {} -> {}", pid(change.from_pid), pid(change.to_pid))?;
+            if let Some(app) = &change.to_app {
+                write!(f, " ({})", app)?;
+            }
+        }
+        if self.dry_run {
+            write!(f, " [dry run]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Filter for querying recent events (unset fields match everything)
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub pid: Option<u32>,
+    /// Case-insensitive process name
+    pub app: Option<String>,
+    /// Only events at or after this Unix time in milliseconds
+    pub since_ms: Option<u64>,
+    /// Maximum number of events returned (newest are kept)
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        self.pid.map_or(true, |pid| event.pid == pid)
+            && self.app.as_ref().map_or(true, |app| event.app.eq_ignore_ascii_case(app))
+            && self.since_ms.map_or(true, |since| event.timestamp_ms >= since)
+    }
+}
+
+/// In-memory ring buffer of audit events with an optional JSONL sink
+pub struct AuditLog {
+    events: VecDeque<AuditEvent>,
+    capacity: usize,
+    file: Option<File>,
+    file_path: Option<PathBuf>,
+}
+
+impl AuditLog {
+    /// Creates an empty log keeping at most `capacity` events in memory
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            file: None,
+            file_path: None,
+        }
+    }
+
+    /// Starts or stops appending events to a JSONL file
+    pub fn set_file(&mut self, path: Option<PathBuf>) {
+        if path == self.file_path {
+            return;
+        }
+
+        self.file = None;
+        self.file_path = path;
+        if let Some(path) = &self.file_path {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => self.file = Some(file),
+                Err(e) => log::warn!("Failed to open audit log {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Records an event and passes it on to event subscribers
+    pub fn record(&mut self, event: AuditEvent) {
+        log::info!("{}", event);
+        self.write_to_file(&event);
+        events::publish(event.clone().into());
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    fn write_to_file(&mut self, event: &AuditEvent) {
+        self.rotate_if_needed();
+
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+        if let Err(e) = writeln!(file, "{}", line) {
+            log::warn!("Failed to write audit log: {}", e);
+        }
+    }
+
+    /// Moves a full log file aside and starts a new one
+    fn rotate_if_needed(&mut self) {
+        let (Some(file), Some(path)) = (&self.file, &self.file_path) else {
+            return;
+        };
+        if file.metadata().map(|m| m.len() < MAX_FILE_BYTES).unwrap_or(true) {
+            return;
+        }
+
+        let path = path.clone();
+        self.file = None;
+        let _ = fs::rename(&path, path.with_extension("jsonl.old"));
+        self.file = OpenOptions::new().create(true).append(true).open(&path).ok();
+    }
+
+    /// Gets the newest `limit` events, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<AuditEvent> {
+        self.query(&AuditQuery {
+            limit: Some(limit),
+            ..Default::default()
+        })
+    }
+
+    /// Gets the events matching a query, oldest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEvent> {
+        let mut matches: Vec<AuditEvent> = self
+            .events
+            .iter()
+            .rev()
+            .filter(|event| query.matches(event))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        matches.reverse();
+        matches
+    }
+
+    /// Gets the number of events held in memory
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Gets the current Unix time in milliseconds
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(pid: u32, app: &str, timestamp_ms: u64) -> AuditEvent {
+        AuditEvent {
+            timestamp_ms,
+            pid,
+            app: app.to_string(),
+            action: AuditAction::Muted,
+            reason: ReasonCode::Background,
+            focus_change: None,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oldest() {
+        let mut log = AuditLog::new(3);
+        for i in 0..5 {
+            log.record(event(i, "app.exe", i as u64));
+        }
+
+        assert_eq!(log.len(), 3);
+        let pids: Vec<u32> = log.recent(10).iter().map(|e| e.pid).collect();
+        assert_eq!(pids, vec![2, 3, 4]);
+        let pids: Vec<u32> = log.recent(2).iter().map(|e| e.pid).collect();
+        assert_eq!(pids, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let mut log = AuditLog::new(10);
+        log.record(event(1, "Chrome.exe", 100));
+        log.record(event(2, "spotify.exe", 200));
+        log.record(event(1, "chrome.exe", 300));
+
+        let by_app = log.query(&AuditQuery {
+            app: Some("chrome.exe".into()),
+            ..Default::default()
+        });
+        assert_eq!(by_app.len(), 2);
+
+        let since = log.query(&AuditQuery {
+            since_ms: Some(200),
+            ..Default::default()
+        });
+        assert_eq!(since.len(), 2);
+
+        let by_pid = log.query(&AuditQuery {
+            pid: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(by_pid[0].app, "spotify.exe");
+    }
+
+    #[test]
+    fn test_json_line_format() {
+        let mut e = event(42, "chrome.exe", 1_000);
+        e.reason = ReasonCode::AlwaysMuted;
+        e.focus_change = Some(FocusChange {
+            from_pid: Some(42),
+            to_pid: Some(7),
+            to_app: Some("game.exe".into()),
+        });
+
+        let json = serde_json::to_string(&e).unwrap();
+        assert!(json.contains("\"action\":\"muted\""));
+        assert!(json.contains("\"reason\":\"always_muted\""));
+        assert!(json.contains("\"to_app\":\"game.exe\""));
+        assert_eq!(serde_json::from_str::<AuditEvent>(&json).unwrap(), e);
+    }
+
+    #[test]
+    fn test_dry_run_flag() {
+        let mut e = event(1, "chrome.exe", 1_000);
+        e.dry_run = true;
+        assert!(e.to_string().ends_with("[dry run]"));
+
+        // Lines written before dry-run existed still parse
+        let json = serde_json::to_string(&event(1, "chrome.exe", 1_000)).unwrap();
+        let old_line = json.replace(",\"dry_run\":false", "");
+        assert!(!serde_json::from_str::<AuditEvent>(&old_line).unwrap().dry_run);
+    }
+
+    #[test]
+    fn test_reason_codes_match_serde() {
+        let reasons = [ReasonCode::SessionGone, ReasonCode::CurrentWorkspace, ReasonCode::Disabled];
+        for reason in reasons {
+            let json = serde_json::to_string(&reason).unwrap();
+            assert_eq!(json, format!("\"{}\"", reason.as_str()));
+        }
+        assert_eq!(ReasonCode::from(Decision::Background), ReasonCode::Background);
+    }
+}
@@ -0,0 +1,194 @@
+//! Wall-clock helpers
+//! Parses user-entered durations and deadlines ("30m", "1h30m", "18:00")
+//! into Unix time, using the local time zone for times of day.
+
+use std::time::Duration;
+use windows::Win32::Foundation::FILETIME;
+use windows::Win32::Storage::FileSystem::FileTimeToLocalFileTime;
+use windows::Win32::System::SystemInformation::GetSystemTimeAsFileTime;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Gets the current offset of local time from UTC in seconds (DST included)
+pub fn local_utc_offset_secs() -> i64 {
+    let ticks = |ft: FILETIME| {
+        (((ft.dwHighDateTime as u64) << 32) | ft.dwLowDateTime as u64) as i64
+    };
+
+    unsafe {
+        let utc = GetSystemTimeAsFileTime();
+        let mut local = FILETIME::default();
+        if FileTimeToLocalFileTime(&utc, &mut local).is_err() {
+            return 0;
+        }
+
+        // FILETIME counts 100ns ticks
+        (ticks(local) - ticks(utc)) / 10_000_000
+    }
+}
+
+/// Parses a duration such as "45" (minutes), "30m", "2h", "1h30m" or "1d"
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().to_lowercase();
+    if text.is_empty() {
+        return None;
+    }
+
+    // A bare number means minutes
+    if let Ok(minutes) = text.parse::<u64>() {
+        return minutes.checked_mul(60).map(Duration::from_secs);
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let value: u64 = number.parse().ok()?;
+        number.clear();
+        let unit_secs = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        // Absurdly long durations overflow; treat them as invalid
+        total = total.checked_add(value.checked_mul(unit_secs)?)?;
+    }
+
+    // Trailing digits without a unit ("1h30") are ambiguous
+    if !number.is_empty() || total == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+/// Parses a deadline into Unix seconds: either a duration from now or a
+/// local time of day ("18:00"); a time that already passed today means tomorrow
+pub fn parse_deadline(text: &str, now: u64, utc_offset_secs: i64) -> Option<u64> {
+    if let Some((hours, minutes)) = text.trim().split_once(':') {
+        let hours: i64 = hours.trim().parse().ok()?;
+        let minutes: i64 = minutes.trim().parse().ok()?;
+        if !(0..24).contains(&hours) || !(0..60).contains(&minutes) {
+            return None;
+        }
+
+        let local_now = now as i64 + utc_offset_secs;
+        let local_midnight = local_now - local_now.rem_euclid(SECS_PER_DAY);
+        let mut local_deadline = local_midnight + hours * 3600 + minutes * 60;
+        if local_deadline <= local_now {
+            local_deadline += SECS_PER_DAY;
+        }
+        return Some((local_deadline - utc_offset_secs) as u64);
+    }
+
+    parse_duration(text).and_then(|d| now.checked_add(d.as_secs()))
+}
+
+/// Gets the Unix time of the next local midnight
+pub fn end_of_local_day(now: u64, utc_offset_secs: i64) -> u64 {
+    let local_now = now as i64 + utc_offset_secs;
+    let local_midnight = local_now - local_now.rem_euclid(SECS_PER_DAY) + SECS_PER_DAY;
+    (local_midnight - utc_offset_secs) as u64
+}
+
+/// Formats a Unix time as a local "HH:MM"
+pub fn format_local_time(unix: u64, utc_offset_secs: i64) -> String {
+    let secs_of_day = (unix as i64 + utc_offset_secs).rem_euclid(SECS_PER_DAY);
+    format!("{:02}:{:02}", secs_of_day / 3600, secs_of_day % 3600 / 60)
+}
+
+/// Formats a deadline as a local "HH:MM", with the date ("YYYY-MM-DD HH:MM")
+/// when it isn't today
+pub fn format_local_deadline(unix: u64, now: u64, utc_offset_secs: i64) -> String {
+    let local_day = |t: u64| (t as i64 + utc_offset_secs).div_euclid(SECS_PER_DAY);
+    let time = format_local_time(unix, utc_offset_secs);
+    if local_day(unix) == local_day(now) {
+        return time;
+    }
+    let (year, month, day) = civil_from_days(local_day(unix));
+    format!("{:04}-{:02}-{:02} {}", year, month, day, time)
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) date
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // Howard Hinnant's algorithm, with eras of 400 years starting in March
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-10-18 12:00:00 UTC
+    const NOON_UTC: u64 = 1_792_324_800;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45"), Some(Duration::from_secs(45 * 60)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration(" 2H "), Some(Duration::from_secs(2 * 3600)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86_400)));
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn test_parse_duration_overflow() {
+        assert_eq!(parse_duration("307445734561825861"), None);
+        assert_eq!(parse_duration("99999999999999999999d"), None);
+        assert_eq!(parse_duration("213503982334602d"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+        assert_eq!(parse_deadline("18446744073709551615s", NOON_UTC, 0), None);
+    }
+
+    #[test]
+    fn test_parse_deadline() {
+        // UTC+2: local time is 14:00
+        let offset = 2 * 3600;
+        assert_eq!(parse_deadline("18:00", NOON_UTC, offset), Some(NOON_UTC + 4 * 3600));
+        // 09:00 already passed today, so it means tomorrow
+        assert_eq!(parse_deadline("09:00", NOON_UTC, offset), Some(NOON_UTC + 19 * 3600));
+        assert_eq!(parse_deadline("1h", NOON_UTC, offset), Some(NOON_UTC + 3600));
+        assert_eq!(parse_deadline("25:00", NOON_UTC, offset), None);
+    }
+
+    #[test]
+    fn test_end_of_local_day() {
+        assert_eq!(end_of_local_day(NOON_UTC, 0), NOON_UTC + 12 * 3600);
+        assert_eq!(end_of_local_day(NOON_UTC, -5 * 3600), NOON_UTC + 17 * 3600);
+    }
+
+    #[test]
+    fn test_format_local_time() {
+        assert_eq!(format_local_time(NOON_UTC, 0), "12:00");
+        assert_eq!(format_local_time(NOON_UTC, 5 * 3600 + 30 * 60), "17:30");
+    }
+
+    #[test]
+    fn test_format_local_deadline() {
+        let later = NOON_UTC + 3 * 3600;
+        assert_eq!(format_local_deadline(later, NOON_UTC, 0), "15:00");
+        // Past local midnight the date is shown
+        assert_eq!(format_local_deadline(later, NOON_UTC, 10 * 3600), "2026-10-19 01:00");
+        assert_eq!(format_local_deadline(NOON_UTC + 80 * 86_400, NOON_UTC, 0), "2027-01-06 12:00");
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+}
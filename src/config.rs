@@ -1,14 +1,50 @@
 //! Configuration and persistence module
 //! Handles saving and loading of application settings and exclusion lists
 
+use crate::config_format::ConfigFormat;
+use crate::layers;
+use crate::migrations;
+use crate::persist;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Environment variable that points at the config file
+pub const CONFIG_ENV_VAR: &str = "BG_MUTER_CONFIG";
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// Preferred over config.json when present, for a config with comments
+const TOML_CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Resolved once per process so every reader and writer agrees
+static CONFIG_PATH: OnceLock<(PathBuf, ConfigSource)> = OnceLock::new();
+
+/// Where the config file location came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// `--config <path>`
+    CommandLine,
+    /// The `BG_MUTER_CONFIG` environment variable
+    Environment,
+    /// A config.json next to the executable
+    Portable,
+    /// The per-user config directory
+    UserProfile,
+}
 
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Version of the file layout, used to migrate older files on load
+    /// (files from before versioning have none and count as 0)
+    #[serde(default)]
+    pub schema_version: u32,
+
     /// List of excluded process names (e.g., "spotify.exe")
     #[serde(default)]
     pub excluded_apps: HashSet<String>,
@@ -44,6 +80,186 @@ pub struct Config {
     /// Window position and size
     #[serde(default)]
     pub window_state: Option<WindowState>,
+
+    /// Whether to mute everything while the user is away (locked or idle)
+    #[serde(default)]
+    pub mute_when_away: bool,
+
+    /// Whether a locked workstation counts as away
+    #[serde(default = "default_mute_on_lock")]
+    pub mute_on_lock: bool,
+
+    /// Minutes without input before the user counts as away (0 = lock only)
+    #[serde(default = "default_away_idle_minutes")]
+    pub away_idle_minutes: u64,
+
+    /// Apps that keep playing while the user is away
+    #[serde(default)]
+    pub away_allowed_apps: HashSet<String>,
+
+    /// Processes that don't take over audibility when focused (shell, launchers)
+    #[serde(default = "default_focus_transparent_apps")]
+    pub focus_transparent_apps: HashSet<String>,
+
+    /// Window classes that don't take over audibility when focused (taskbar, desktop)
+    #[serde(default = "default_focus_transparent_classes")]
+    pub focus_transparent_classes: HashSet<String>,
+
+    /// Which apps besides the foreground one stay audible
+    #[serde(default)]
+    pub audibility_policy: AudibilityPolicy,
+
+    /// Global hotkey bindings
+    #[serde(default)]
+    pub hotkeys: HotkeyBindings,
+
+    /// Muting is snoozed until this time (Unix seconds), if set
+    #[serde(default)]
+    pub snoozed_until: Option<u64>,
+
+    /// Apps exempted from muting until a deadline (process name -> Unix seconds)
+    #[serde(default)]
+    pub temporary_exemptions: HashMap<String, u64>,
+
+    /// Whether mute/unmute decisions are also appended to audit.jsonl
+    #[serde(default)]
+    pub audit_log_to_file: bool,
+
+    /// Decide and log as usual, but never actually mute or unmute anything
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Whether the local HTTP/WebSocket API is served (see `http_api`)
+    #[serde(default)]
+    pub http_api_enabled: bool,
+
+    /// Port of the HTTP API on 127.0.0.1
+    #[serde(default = "default_http_api_port")]
+    pub http_api_port: u16,
+
+    /// Token HTTP API clients must send; generated when the API is first enabled
+    #[serde(default)]
+    pub http_api_token: String,
+
+    /// Rhai script that decides sessions before the built-in rules (see `scripting`);
+    /// relative paths are next to config.json
+    #[serde(default)]
+    pub policy_script: Option<PathBuf>,
+
+    /// Commands run when events happen
+    #[serde(default)]
+    pub hooks: HookCommands,
+
+    /// Hooks still running after this many seconds are killed
+    #[serde(default = "default_hook_timeout_secs")]
+    pub hook_timeout_secs: u64,
+
+    /// Set when config.json could not be parsed at startup; while set,
+    /// `save` leaves the file alone until the user saves explicitly
+    #[serde(skip)]
+    pub load_error: Option<ConfigError>,
+}
+
+/// A config file that could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub message: String,
+    /// 1-based line of the error (0 if not a syntax/data error)
+    pub line: usize,
+    /// 1-based column of the error
+    pub column: usize,
+    /// Where a copy of the broken file was saved
+    pub backup: Option<PathBuf>,
+}
+
+impl ConfigError {
+    /// Captures the message and location of a serde_json error
+    pub fn from_json(error: &serde_json::Error) -> Self {
+        // serde_json appends " at line X column Y" to its message
+        let message = error.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(pos) => message[..pos].to_string(),
+            None => message,
+        };
+
+        Self {
+            message,
+            line: error.line(),
+            column: error.column(),
+            backup: None,
+        }
+    }
+
+    /// Locates an error given as a byte offset into `contents`
+    pub fn at_offset(message: &str, contents: &str, offset: usize) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        Self {
+            message: message.trim().to_string(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            backup: None,
+        }
+    }
+
+    /// Wraps an error that has no location in the file (e.g. I/O)
+    pub fn other(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            line: 0,
+            column: 0,
+            backup: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+        } else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
+
+/// Global hotkey bindings such as "Ctrl+Alt+M" (unset actions are not registered)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeyBindings {
+    /// Toggle muting on/off
+    pub toggle_muting: Option<String>,
+    /// Add or remove the foreground app from the exclusion list
+    pub toggle_exclude_current: Option<String>,
+    /// Keep the foreground app audible until pressed again
+    pub pin_current: Option<String>,
+    /// Unmute all background apps while held
+    pub peek: Option<String>,
+}
+
+/// Command lines run by `hooks` for each kind of event (unset events run nothing)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookCommands {
+    pub app_muted: Option<String>,
+    pub app_unmuted: Option<String>,
+    pub foreground_changed: Option<String>,
+    /// Muting was turned on or off from the tray, a hotkey or `ctl`
+    pub muting_toggled: Option<String>,
+}
+
+/// Decides which non-excluded apps stay audible
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudibilityPolicy {
+    /// Only the focused app is audible
+    #[default]
+    Focus,
+    /// Any app with a visible, non-minimized, non-occluded window is audible
+    Visible,
+    /// Any app with a window on the current virtual desktop is audible
+    Workspace,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,9 +290,103 @@ fn default_minimize_button_to_tray() -> bool {
     true
 }
 
+fn default_http_api_port() -> u16 {
+    47800
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    10
+}
+
+fn default_mute_on_lock() -> bool {
+    true
+}
+
+fn default_away_idle_minutes() -> u64 {
+    10
+}
+
+fn default_focus_transparent_apps() -> HashSet<String> {
+    [
+        "startmenuexperiencehost.exe", // Start menu
+        "searchhost.exe",              // Windows 11 search
+        "searchapp.exe",               // Windows 10 search
+        "shellexperiencehost.exe",     // Action center, tray flyouts
+        "textinputhost.exe",           // Touch keyboard, emoji panel
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_focus_transparent_classes() -> HashSet<String> {
+    [
+        "shell_traywnd",                // Taskbar
+        "shell_secondarytraywnd",       // Taskbar on secondary monitors
+        "notifyiconoverflowwindow",     // Tray overflow flyout
+        "progman",                      // Desktop
+        "workerw",                      // Desktop (wallpaper host)
+        "multitaskingviewframe",        // Alt+Tab switcher
+        "xamlexplorerhostislandwindow", // Task view / Alt+Tab on Windows 11
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// Current time as Unix seconds
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Picks the config file from an explicit path, the environment variable,
+/// a portable config.json in `exe_dir`, or the per-user config directory
+fn resolve_config_path(
+    explicit: Option<PathBuf>,
+    env: Option<OsString>,
+    exe_dir: Option<&Path>,
+    config_dir: Option<PathBuf>,
+) -> (PathBuf, ConfigSource) {
+    let requested = match (explicit, env.filter(|v| !v.is_empty())) {
+        (Some(path), _) => Some((path, ConfigSource::CommandLine)),
+        (None, Some(path)) => Some((PathBuf::from(path), ConfigSource::Environment)),
+        (None, None) => None,
+    };
+    if let Some((path, source)) = requested {
+        // Pointing at a folder means the config file inside it
+        let path = if path.is_dir() { config_file_in(&path) } else { path };
+        // Joining an absolute path keeps it as is
+        let path = std::env::current_dir().map(|cwd| cwd.join(&path)).unwrap_or(path);
+        return (path, source);
+    }
+
+    if let Some(portable) = exe_dir.map(config_file_in) {
+        if portable.exists() {
+            return (portable, ConfigSource::Portable);
+        }
+    }
+
+    let dir = config_dir.unwrap_or_else(|| PathBuf::from(".")).join("rust-bg-muter");
+    (config_file_in(&dir), ConfigSource::UserProfile)
+}
+
+/// Gets config.toml in `dir` if it exists, otherwise config.json
+fn config_file_in(dir: &Path) -> PathBuf {
+    let toml = dir.join(TOML_CONFIG_FILE_NAME);
+    if toml.exists() {
+        toml
+    } else {
+        dir.join(CONFIG_FILE_NAME)
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            schema_version: migrations::CURRENT_VERSION,
             excluded_apps: HashSet::new(),
             always_muted_apps: HashSet::new(),
             muting_enabled: true,
@@ -86,6 +396,25 @@ impl Default for Config {
             minimize_button_to_tray: true,
             start_with_windows: false,
             window_state: None,
+            mute_when_away: false,
+            mute_on_lock: true,
+            away_idle_minutes: 10,
+            away_allowed_apps: HashSet::new(),
+            focus_transparent_apps: default_focus_transparent_apps(),
+            focus_transparent_classes: default_focus_transparent_classes(),
+            audibility_policy: AudibilityPolicy::Focus,
+            hotkeys: HotkeyBindings::default(),
+            snoozed_until: None,
+            temporary_exemptions: HashMap::new(),
+            audit_log_to_file: false,
+            dry_run: false,
+            http_api_enabled: false,
+            http_api_port: default_http_api_port(),
+            http_api_token: String::new(),
+            policy_script: None,
+            hooks: HookCommands::default(),
+            hook_timeout_secs: default_hook_timeout_secs(),
+            load_error: None,
         }
     }
 }
@@ -93,62 +422,240 @@ impl Default for Config {
 #[allow(dead_code)]
 impl Config {
     /// Gets the config file path
+    ///
+    /// In order of precedence: `--config <path>` (see `set_config_path`),
+    /// the `BG_MUTER_CONFIG` environment variable, a config.json next to the
+    /// executable (portable mode), then `%APPDATA%\rust-bg-muter\config.json`.
+    /// Directories are created when the file is first written, not here.
     pub fn config_path() -> PathBuf {
-        let config_dir = dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("rust-bg-muter");
-        
-        if !config_dir.exists() {
-            let _ = fs::create_dir_all(&config_dir);
-        }
-        
-        config_dir.join("config.json")
+        Self::config_location().0.clone()
+    }
+
+    /// Gets the config file path and where it came from
+    pub fn config_location() -> &'static (PathBuf, ConfigSource) {
+        CONFIG_PATH.get_or_init(|| {
+            // Unit tests never write the user's config
+            if cfg!(test) {
+                let dir = std::env::temp_dir()
+                    .join(format!("rust-bg-muter-test-{}", std::process::id()));
+                return resolve_config_path(Some(dir.join("config.json")), None, None, None);
+            }
+            let exe_dir = std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Path::to_path_buf));
+            resolve_config_path(
+                None,
+                std::env::var_os(CONFIG_ENV_VAR),
+                exe_dir.as_deref(),
+                dirs::config_dir(),
+            )
+        })
+    }
+
+    /// Uses `path` as the config file (from the command line)
+    /// Must be called before the config is first loaded; returns false otherwise.
+    pub fn set_config_path(path: PathBuf) -> bool {
+        CONFIG_PATH
+            .set(resolve_config_path(Some(path), None, None, None))
+            .is_ok()
+    }
+
+    /// Gets the audit log path (next to the config file)
+    pub fn audit_log_path() -> PathBuf {
+        Self::config_path().with_file_name("audit.jsonl")
+    }
+
+    /// Gets the full path of the policy script, if one is set
+    pub fn policy_script_path(&self) -> Option<PathBuf> {
+        let path = self.policy_script.as_ref().filter(|p| !p.as_os_str().is_empty())?;
+        Some(match Self::config_path().parent() {
+            Some(dir) => dir.join(path),
+            None => path.clone(),
+        })
     }
 
     /// Loads configuration from disk
+    ///
+    /// A file that fails to parse is backed up and left untouched: the app
+    /// runs on defaults with `load_error` set until the user saves explicitly.
     pub fn load() -> Self {
         let path = Self::config_path();
         
         if path.exists() {
             match fs::read_to_string(&path) {
-                Ok(contents) => {
-                    match serde_json::from_str(&contents) {
-                        Ok(config) => return config,
-                        Err(e) => {
-                            log::error!("Failed to parse config: {}", e);
+                // An empty file (e.g. created to enable portable mode) gets the defaults
+                Ok(contents) if contents.trim().is_empty() => {}
+                Ok(contents) => match Self::parse(&contents, ConfigFormat::from_path(&path)) {
+                    Ok((config, None)) => return config,
+                    Ok((config, Some(version))) => {
+                        // Keep the old file: a downgrade can't read the new schema
+                        let backup = persist::sibling_path(&path, &format!("v{}", version));
+                        match fs::copy(&path, &backup) {
+                            Ok(_) => {
+                                log::info!("Backed up schema {} config to {:?}", version, backup);
+                                let _ = config.save();
+                            }
+                            Err(e) => log::error!("Failed to back up config before migrating: {}", e),
                         }
+                        return config;
                     }
-                }
+                    Err(mut error) => {
+                        log::error!("Failed to parse config: {}", error);
+                        error.backup = Self::backup_broken_file(&path);
+                        return Self {
+                            load_error: Some(error),
+                            ..Self::layered_defaults()
+                        };
+                    }
+                },
                 Err(e) => {
                     log::error!("Failed to read config: {}", e);
+                    return Self {
+                        load_error: Some(ConfigError::other(e)),
+                        ..Self::layered_defaults()
+                    };
                 }
             }
         }
         
-        // Return default config if loading fails
-        let default = Self::default();
+        // First run: write the defaults
+        let default = Self::layered_defaults();
         let _ = default.save();
         default
     }
 
+    /// Gets the defaults with the policy and overrides applied
+    fn layered_defaults() -> Self {
+        if layers::current().is_empty() {
+            return Self::default();
+        }
+        let doc = serde_json::json!({ "schema_version": migrations::CURRENT_VERSION });
+        Self::from_user_document(doc).unwrap_or_default()
+    }
+
+    /// Builds the effective config from the user's (current-schema) document
+    fn from_user_document(mut doc: serde_json::Value) -> Result<Self, ConfigError> {
+        let layers = layers::current();
+        layers.remember_user_values(&doc);
+        layers.apply(&mut doc);
+        serde_json::from_value(doc).map_err(ConfigError::other)
+    }
+
+    /// Parses config file contents, migrating older schema versions and
+    /// applying the policy and overrides
+    /// Returns the config and the version it was migrated from, if any.
+    pub fn parse(
+        contents: &str,
+        format: ConfigFormat,
+    ) -> Result<(Self, Option<u32>), ConfigError> {
+        let mut doc = format.parse(contents)?;
+
+        let migrated = migrations::migrate(&mut doc).map_err(ConfigError::other)?;
+        if migrated.is_none() {
+            // Check the text itself so type errors keep their line and column
+            format.deserialize::<Self>(contents)?;
+        }
+        Ok((Self::from_user_document(doc)?, migrated))
+    }
+
+    /// Copies an unparsable config file to `config.json.broken-<unix time>`
+    fn backup_broken_file(path: &std::path::Path) -> Option<PathBuf> {
+        let backup = persist::sibling_path(path, &format!("broken-{}", unix_now()));
+        match fs::copy(path, &backup) {
+            Ok(_) => {
+                log::warn!("Backed up unparsable config to {:?}", backup);
+                Some(backup)
+            }
+            Err(e) => {
+                log::error!("Failed to back up unparsable config: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Saves to disk on the user's explicit request, replacing a config
+    /// file that failed to load
+    pub fn save_explicitly(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_error = None;
+        self.save()
+    }
+
+    /// Whether `save` may write the file (not while a broken config file
+    /// is waiting for an explicit save)
+    fn saves_implicitly(&self) -> bool {
+        self.load_error.is_none()
+    }
+
     /// Saves configuration to disk
+    /// (skipped while a broken config file is waiting for an explicit save)
+    ///
+    /// Only serializes here; the atomic write happens on the persistence
+    /// thread, so bursts of changes end up as a single write.
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let path = Self::config_path();
-        
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+        if !self.saves_implicitly() {
+            log::warn!("Not saving over the unparsable config file");
+            return Ok(());
         }
-        
-        let contents = serde_json::to_string_pretty(self)?;
-        fs::write(&path, contents)?;
-        
-        log::info!("Config saved to {:?}", path);
+
+        // Policy and override values are not the user's to save
+        let mut doc = serde_json::to_value(self)?;
+        layers::current().to_user_document(&mut doc);
+
+        // A TOML file is edited in place to keep the user's comments
+        let path = Self::config_path();
+        let format = ConfigFormat::from_path(&path);
+        let previous = match format {
+            ConfigFormat::Toml => fs::read_to_string(&path).ok(),
+            ConfigFormat::Json => None,
+        };
+        let contents = format.render(&doc, previous.as_deref())?;
+        persist::save_in_background(path, contents);
         Ok(())
     }
 
+    /// Fixes up values edited by hand: lowercases app names and clamps the poll interval
+    pub fn normalize(&mut self) {
+        for apps in [
+            &mut self.excluded_apps,
+            &mut self.always_muted_apps,
+            &mut self.away_allowed_apps,
+            &mut self.focus_transparent_apps,
+            &mut self.focus_transparent_classes,
+        ] {
+            *apps = apps.drain().map(|app| app.to_lowercase()).collect();
+        }
+        self.temporary_exemptions = self
+            .temporary_exemptions
+            .drain()
+            .map(|(app, until)| (app.to_lowercase(), until))
+            .collect();
+        self.poll_interval_ms = self.poll_interval_ms.clamp(100, 2000);
+    }
+
+    /// Whether the user may change `key` (not locked by policy or an override)
+    pub fn is_editable(key: &str) -> bool {
+        let editable = !layers::current().is_locked(key);
+        if !editable {
+            log::info!("Not changing {}: it is locked by policy or an override", key);
+        }
+        editable
+    }
+
+    /// Whether the user may remove `app_name` from the app list `key`
+    /// (entries required by the policy stay)
+    pub fn is_list_entry_removable(key: &str, app_name: &str) -> bool {
+        if layers::current().policy_contains(key, app_name) {
+            log::info!("Not removing {} from {}: it is required by policy", app_name, key);
+            return false;
+        }
+        Self::is_editable(key)
+    }
+
     /// Adds an app to the exclusion list
     pub fn add_excluded_app(&mut self, app_name: &str) {
+        if !Self::is_editable("excluded_apps") {
+            return;
+        }
         let normalized = app_name.to_lowercase();
         self.excluded_apps.insert(normalized);
         let _ = self.save();
@@ -156,6 +663,9 @@ impl Config {
 
     /// Removes an app from the exclusion list
     pub fn remove_excluded_app(&mut self, app_name: &str) {
+        if !Self::is_list_entry_removable("excluded_apps", app_name) {
+            return;
+        }
         let normalized = app_name.to_lowercase();
         self.excluded_apps.remove(&normalized);
         let _ = self.save();
@@ -169,6 +679,9 @@ impl Config {
 
     /// Adds an app to the always-muted list
     pub fn add_always_muted_app(&mut self, app_name: &str) {
+        if !Self::is_editable("always_muted_apps") {
+            return;
+        }
         let normalized = app_name.to_lowercase();
         self.always_muted_apps.insert(normalized);
         let _ = self.save();
@@ -176,6 +689,9 @@ impl Config {
 
     /// Removes an app from the always-muted list
     pub fn remove_always_muted_app(&mut self, app_name: &str) {
+        if !Self::is_list_entry_removable("always_muted_apps", app_name) {
+            return;
+        }
         let normalized = app_name.to_lowercase();
         self.always_muted_apps.remove(&normalized);
         let _ = self.save();
@@ -187,18 +703,203 @@ impl Config {
         self.always_muted_apps.contains(&normalized)
     }
 
-    /// Toggles muting functionality
+    /// Adds an app to the away allowlist
+    pub fn add_away_allowed_app(&mut self, app_name: &str) {
+        if !Self::is_editable("away_allowed_apps") {
+            return;
+        }
+        let normalized = app_name.to_lowercase();
+        self.away_allowed_apps.insert(normalized);
+        let _ = self.save();
+    }
+
+    /// Removes an app from the away allowlist
+    pub fn remove_away_allowed_app(&mut self, app_name: &str) {
+        if !Self::is_list_entry_removable("away_allowed_apps", app_name) {
+            return;
+        }
+        let normalized = app_name.to_lowercase();
+        self.away_allowed_apps.remove(&normalized);
+        let _ = self.save();
+    }
+
+    /// Checks if an app may keep playing while the user is away
+    pub fn is_away_allowed(&self, app_name: &str) -> bool {
+        let normalized = app_name.to_lowercase();
+        self.away_allowed_apps.contains(&normalized)
+    }
+
+    /// Checks if focusing this process keeps the previous app audible
+    pub fn is_focus_transparent_app(&self, app_name: &str) -> bool {
+        let normalized = app_name.to_lowercase();
+        self.focus_transparent_apps.contains(&normalized)
+    }
+
+    /// Checks if focusing a window of this class keeps the previous app audible
+    pub fn is_focus_transparent_class(&self, class_name: &str) -> bool {
+        let normalized = class_name.to_lowercase();
+        self.focus_transparent_classes.contains(&normalized)
+    }
+
+    /// Gets the idle time after which the user counts as away (None = lock only)
+    pub fn away_idle_threshold(&self) -> Option<std::time::Duration> {
+        if self.away_idle_minutes == 0 {
+            None
+        } else {
+            Some(std::time::Duration::from_secs(self.away_idle_minutes * 60))
+        }
+    }
+
+    /// Adds the app to the exclusion list, or removes it if already there
+    /// Returns true if the app is now excluded.
+    pub fn toggle_excluded_app(&mut self, app_name: &str) -> bool {
+        if self.is_excluded(app_name) {
+            self.remove_excluded_app(app_name);
+        } else {
+            self.add_excluded_app(app_name);
+        }
+        self.is_excluded(app_name)
+    }
+
+    /// Exempts an app from muting until the given Unix time
+    pub fn add_temporary_exemption(&mut self, app_name: &str, until: u64) {
+        if !Self::is_editable("temporary_exemptions") {
+            return;
+        }
+        let normalized = app_name.to_lowercase();
+        self.temporary_exemptions.insert(normalized, until);
+        let _ = self.save();
+    }
+
+    /// Removes a temporary exemption
+    pub fn remove_temporary_exemption(&mut self, app_name: &str) {
+        if !Self::is_editable("temporary_exemptions") {
+            return;
+        }
+        let normalized = app_name.to_lowercase();
+        if self.temporary_exemptions.remove(&normalized).is_some() {
+            let _ = self.save();
+        }
+    }
+
+    /// Checks if an app has an unexpired temporary exemption
+    pub fn is_temporarily_exempt(&self, app_name: &str) -> bool {
+        let normalized = app_name.to_lowercase();
+        self.temporary_exemptions
+            .get(&normalized)
+            .map(|until| *until > unix_now())
+            .unwrap_or(false)
+    }
+
+    /// Gets the apps with unexpired temporary exemptions
+    pub fn active_exemptions(&self) -> HashSet<String> {
+        let now = unix_now();
+        self.temporary_exemptions
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(app, _)| app.clone())
+            .collect()
+    }
+
+    /// Checks if any temporary exemption has expired
+    pub fn has_expired_exemptions(&self) -> bool {
+        let now = unix_now();
+        self.temporary_exemptions.values().any(|until| *until <= now)
+    }
+
+    /// Drops expired temporary exemptions, returning the apps that were removed
+    pub fn prune_expired_exemptions(&mut self) -> Vec<String> {
+        let now = unix_now();
+        let expired: Vec<String> = self
+            .temporary_exemptions
+            .iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(app, _)| app.clone())
+            .collect();
+
+        if !expired.is_empty() {
+            for app in &expired {
+                self.temporary_exemptions.remove(app);
+            }
+            let _ = self.save();
+        }
+        expired
+    }
+
+    /// Snoozes muting: everything stays audible until the duration has passed
+    pub fn snooze_for(&mut self, duration: Duration) {
+        self.snoozed_until = Some(unix_now().saturating_add(duration.as_secs()));
+        let _ = self.save();
+    }
+
+    /// Ends an active snooze
+    pub fn cancel_snooze(&mut self) {
+        if self.snoozed_until.take().is_some() {
+            let _ = self.save();
+        }
+    }
+
+    /// Gets the time left on an active snooze (None if not snoozed or expired)
+    pub fn snooze_remaining(&self) -> Option<Duration> {
+        let until = self.snoozed_until?;
+        let now = unix_now();
+        (until > now).then(|| Duration::from_secs(until - now))
+    }
+
+    /// Checks if muting is currently snoozed
+    pub fn is_snoozed(&self) -> bool {
+        self.snooze_remaining().is_some()
+    }
+
+    /// Toggles muting functionality (turning muting on also ends a snooze)
     pub fn toggle_muting(&mut self) -> bool {
+        if !Self::is_editable("muting_enabled") {
+            return self.muting_enabled;
+        }
         self.muting_enabled = !self.muting_enabled;
+        self.snoozed_until = None;
         let _ = self.save();
         self.muting_enabled
     }
 
+    /// Toggles dry-run mode, returning the new state
+    pub fn toggle_dry_run(&mut self) -> bool {
+        if !Self::is_editable("dry_run") {
+            return self.dry_run;
+        }
+        self.dry_run = !self.dry_run;
+        let _ = self.save();
+        self.dry_run
+    }
+
     /// Sets muting state
     pub fn set_muting(&mut self, enabled: bool) {
+        if !Self::is_editable("muting_enabled") {
+            return;
+        }
         self.muting_enabled = enabled;
         let _ = self.save();
     }
+
+    /// Sets one setting from its JSON value, as if edited in the config file
+    pub fn set_value(&mut self, key: &str, value: serde_json::Value) -> Result<(), String> {
+        let mut doc = serde_json::to_value(&*self).map_err(|e| e.to_string())?;
+        let Some(slot) = doc.get_mut(key).filter(|_| key != "schema_version") else {
+            return Err(format!("unknown setting {:?}", key));
+        };
+        if !Self::is_editable(key) {
+            return Err(format!("{} is locked by policy or an override", key));
+        }
+        *slot = value;
+
+        let mut updated: Self = serde_json::from_value(doc)
+            .map_err(|e| format!("invalid value for {}: {}", key, e))?;
+        updated.normalize();
+        updated.load_error = self.load_error.take();
+        *self = updated;
+        let _ = self.save();
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -212,6 +913,125 @@ mod tests {
         assert!(config.excluded_apps.is_empty());
         assert!(config.always_muted_apps.is_empty());
         assert_eq!(config.poll_interval_ms, 500);
+        assert!(!config.mute_when_away);
+        assert!(config.mute_on_lock);
+        assert!(config.is_focus_transparent_class("Shell_TrayWnd"));
+        assert!(config.is_focus_transparent_app("SearchHost.exe"));
+    }
+
+    #[test]
+    fn test_config_error_location() {
+        let e = serde_json::from_str::<Config>("{\n  \"muting_enabled\": yes\n}").unwrap_err();
+        let error = ConfigError::from_json(&e);
+        assert_eq!(error.line, 2);
+        assert!(error.column > 0);
+        assert!(!error.message.contains(" at line "));
+        assert!(error.to_string().starts_with("line 2, column "));
+    }
+
+    #[test]
+    fn test_resolve_config_path() {
+        let dir = std::env::temp_dir().join(format!("bg-muter-resolve-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let appdata = Some(dir.join("appdata"));
+        let resolve = |explicit: Option<PathBuf>, env: Option<&str>| {
+            let env = env.map(|name| dir.join(name).into());
+            resolve_config_path(explicit, env, Some(&dir), appdata.clone())
+        };
+
+        // Nothing set: per-user directory, which is not created
+        assert_eq!(
+            resolve(None, None),
+            (dir.join("appdata/rust-bg-muter/config.json"), ConfigSource::UserProfile)
+        );
+        assert!(!dir.join("appdata").exists());
+
+        // A config.json next to the executable switches to portable mode
+        fs::write(dir.join("config.json"), "").unwrap();
+        assert_eq!(resolve(None, None), (dir.join("config.json"), ConfigSource::Portable));
+
+        // The environment beats portable mode, the command line beats both
+        assert_eq!(
+            resolve(None, Some("env.json")),
+            (dir.join("env.json"), ConfigSource::Environment)
+        );
+        assert_eq!(
+            resolve(Some(dir.join("cli.json")), Some("env.json")),
+            (dir.join("cli.json"), ConfigSource::CommandLine)
+        );
+
+        // A directory means the config file inside it, preferring TOML
+        assert_eq!(resolve(Some(dir.clone()), None).0, dir.join("config.json"));
+        fs::write(dir.join("config.toml"), "").unwrap();
+        assert_eq!(resolve(Some(dir.clone()), None).0, dir.join("config.toml"));
+        assert_eq!(resolve(None, None), (dir.join("config.toml"), ConfigSource::Portable));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_migrates_old_files() {
+        let old = r#"{"excluded_apps": ["Spotify.exe"]}"#;
+        let (config, from) = Config::parse(old, ConfigFormat::Json).unwrap();
+        assert_eq!(from, Some(0));
+        assert_eq!(config.schema_version, migrations::CURRENT_VERSION);
+        assert!(config.excluded_apps.contains("spotify.exe"));
+
+        // Current files still report where a type error is
+        let current = format!(
+            "{{\n\"schema_version\": {},\n\"muting_enabled\": 1\n}}",
+            migrations::CURRENT_VERSION
+        );
+        assert_eq!(Config::parse(&current, ConfigFormat::Json).unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn test_load_error_blocks_implicit_saves() {
+        let config = Config {
+            load_error: Some(ConfigError {
+                message: "broken".to_string(),
+                line: 1,
+                column: 1,
+                backup: None,
+            }),
+            ..Config::default()
+        };
+        // Must not touch the file on disk until the user saves explicitly
+        assert!(!config.saves_implicitly());
+        assert!(Config::default().saves_implicitly());
+    }
+
+    /// A default config whose saves go to the test config path under temp_dir()
+    fn scratch_config() -> Config {
+        assert!(Config::config_path().starts_with(std::env::temp_dir()));
+        Config::default()
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut config = Config::default();
+        config.excluded_apps.insert("Spotify.EXE".to_string());
+        config.temporary_exemptions.insert("VLC.exe".to_string(), 1);
+        config.poll_interval_ms = 5;
+
+        config.normalize();
+        assert!(config.excluded_apps.contains("spotify.exe"));
+        assert!(config.temporary_exemptions.contains_key("vlc.exe"));
+        assert_eq!(config.poll_interval_ms, 100);
+    }
+
+    #[test]
+    fn test_away_settings() {
+        let mut config = scratch_config();
+        assert_eq!(config.away_idle_threshold(), Some(std::time::Duration::from_secs(600)));
+
+        config.away_idle_minutes = 0;
+        assert_eq!(config.away_idle_threshold(), None);
+
+        config.add_away_allowed_app("Discord.exe");
+        assert!(config.is_away_allowed("discord.exe"));
+        config.remove_away_allowed_app("DISCORD.EXE");
+        assert!(!config.is_away_allowed("discord.exe"));
     }
 
     #[test]
@@ -226,6 +1046,70 @@ mod tests {
         assert!(!config.is_excluded("spotify.exe"));
     }
 
+    #[test]
+    fn test_set_value() {
+        let mut config = scratch_config();
+        config.set_value("poll_interval_ms", serde_json::json!(250)).unwrap();
+        assert_eq!(config.poll_interval_ms, 250);
+        config.set_value("excluded_apps", serde_json::json!(["VLC.exe"])).unwrap();
+        assert!(config.is_excluded("vlc.exe"));
+
+        assert!(config.set_value("poll_interval_ms", serde_json::json!("fast")).is_err());
+        assert!(config.set_value("no_such_key", serde_json::json!(1)).is_err());
+        assert!(config.set_value("schema_version", serde_json::json!(0)).is_err());
+        assert_eq!(config.poll_interval_ms, 250);
+    }
+
+    #[test]
+    fn test_toggle_excluded_app() {
+        let mut config = scratch_config();
+        assert!(config.toggle_excluded_app("Game.exe"));
+        assert!(config.is_excluded("game.exe"));
+        assert!(!config.toggle_excluded_app("game.exe"));
+        assert!(!config.is_excluded("game.exe"));
+    }
+
+    #[test]
+    fn test_snooze() {
+        let mut config = scratch_config();
+        assert!(!config.is_snoozed());
+
+        config.snooze_for(Duration::from_secs(15 * 60));
+        assert!(config.is_snoozed());
+        let remaining = config.snooze_remaining().unwrap();
+        assert!(remaining > Duration::from_secs(14 * 60));
+        assert!(remaining <= Duration::from_secs(15 * 60));
+
+        // Expired snoozes (e.g. from a previous run) don't count
+        config.snoozed_until = Some(unix_now() - 1);
+        assert!(!config.is_snoozed());
+
+        config.snooze_for(Duration::from_secs(60));
+        config.cancel_snooze();
+        assert!(!config.is_snoozed());
+        assert_eq!(config.snoozed_until, None);
+    }
+
+    #[test]
+    fn test_temporary_exemptions() {
+        let mut config = scratch_config();
+        let now = unix_now();
+
+        config.add_temporary_exemption("Spotify.exe", now + 3600);
+        config.add_temporary_exemption("old.exe", now - 1);
+        assert!(config.is_temporarily_exempt("spotify.exe"));
+        assert!(!config.is_temporarily_exempt("old.exe"));
+        assert!(config.active_exemptions().contains("spotify.exe"));
+        assert!(config.has_expired_exemptions());
+
+        assert_eq!(config.prune_expired_exemptions(), vec!["old.exe".to_string()]);
+        assert!(!config.has_expired_exemptions());
+        assert_eq!(config.temporary_exemptions.len(), 1);
+
+        config.remove_temporary_exemption("SPOTIFY.EXE");
+        assert!(config.temporary_exemptions.is_empty());
+    }
+
     #[test]
     fn test_serialization() {
         let mut config = Config::default();
@@ -238,4 +1122,18 @@ mod tests {
         assert!(loaded.is_excluded("test.exe"));
         assert!(loaded.is_always_muted("always.exe"));
     }
+
+    #[test]
+    fn test_audibility_policy_serialization() {
+        let json = r#"{ "audibility_policy": "visible" }"#;
+        let loaded: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(loaded.audibility_policy, AudibilityPolicy::Visible);
+
+        let json = r#"{ "audibility_policy": "workspace" }"#;
+        let loaded: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(loaded.audibility_policy, AudibilityPolicy::Workspace);
+
+        let loaded: Config = serde_json::from_str("{}").unwrap();
+        assert_eq!(loaded.audibility_policy, AudibilityPolicy::Focus);
+    }
 }
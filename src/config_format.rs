@@ -0,0 +1,201 @@
+//! Config file format module
+//! Reads config.json or config.toml into a JSON document and writes it back.
+//! TOML files are edited in place, so comments and key order survive saves.
+
+use crate::config::ConfigError;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// Written at the top of a new TOML config
+const TOML_HEADER: &str = "# Background Muter settings. Comments you add here are kept when the app saves.\n\n";
+
+/// The syntax of a config file, chosen by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// `.toml` files are TOML, anything else is JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+
+    /// Parses file contents into a JSON document
+    pub fn parse(self, contents: &str) -> Result<Value, ConfigError> {
+        self.deserialize(contents)
+    }
+
+    /// Deserializes file contents, reporting where they don't fit `T`
+    pub fn deserialize<T: DeserializeOwned>(self, contents: &str) -> Result<T, ConfigError> {
+        match self {
+            Self::Json => serde_json::from_str(contents).map_err(|e| ConfigError::from_json(&e)),
+            Self::Toml => toml_edit::de::from_str(contents).map_err(|e| {
+                let offset = e.span().map(|span| span.start).unwrap_or(0);
+                ConfigError::at_offset(e.message(), contents, offset)
+            }),
+        }
+    }
+
+    /// Renders a document; for TOML, edits `previous` so only changed
+    /// values are rewritten and everything else keeps its comments
+    pub fn render(self, doc: &Value, previous: Option<&str>) -> Result<String, String> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(doc).map_err(|e| e.to_string()),
+            Self::Toml => {
+                // TOML has no null; unset options are simply left out
+                let rendered = toml_edit::ser::to_document(&without_nulls(doc))
+                    .map_err(|e| e.to_string())?;
+
+                match previous.and_then(|text| text.parse::<DocumentMut>().ok()) {
+                    Some(mut existing) => {
+                        update_table(existing.as_table_mut(), rendered.as_table());
+                        Ok(existing.to_string())
+                    }
+                    None => Ok(format!("{}{}", TOML_HEADER, rendered)),
+                }
+            }
+        }
+    }
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), without_nulls(v)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Makes `old` hold the same keys and values as `new`, touching only what changed
+fn update_table(old: &mut dyn TableLike, new: &dyn TableLike) {
+    let stale: Vec<String> = old
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in stale {
+        old.remove(&key);
+    }
+
+    for (key, new_item) in new.iter() {
+        match old.get_mut(key) {
+            Some(old_item) => update_item(old_item, new_item),
+            None => {
+                old.insert(key, new_item.clone());
+            }
+        }
+    }
+}
+
+fn update_item(old: &mut Item, new: &Item) {
+    if let (Some(old_table), Some(new_table)) = (old.as_table_like_mut(), new.as_table_like()) {
+        update_table(old_table, new_table);
+        return;
+    }
+
+    match (old, new) {
+        (Item::Value(toml_edit::Value::Array(old)), Item::Value(toml_edit::Value::Array(new))) => {
+            // Config arrays are sets: keep surviving entries where the user put them
+            old.retain(|v| new.iter().any(|n| same_value(v, n)));
+            for value in new.iter() {
+                if !old.iter().any(|v| same_value(v, value)) {
+                    old.push(value.clone());
+                }
+            }
+        }
+        (Item::Value(old), Item::Value(new)) => {
+            if !same_value(old, new) {
+                let decor = old.decor().clone();
+                *old = new.clone();
+                *old.decor_mut() = decor;
+            }
+        }
+        (old, new) => *old = new.clone(),
+    }
+}
+
+/// Compares values ignoring the whitespace and comments around them
+fn same_value(a: &toml_edit::Value, b: &toml_edit::Value) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    a.decor_mut().clear();
+    b.decor_mut().clear();
+    a.to_string() == b.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const COMMENTED: &str = r#"# Work laptop
+schema_version = 1
+muting_enabled = true # turned off in meetings
+poll_interval_ms = 500
+
+# Never mute these
+excluded_apps = [
+    "spotify.exe", # music
+    "discord.exe",
+]
+
+[hotkeys]
+# Quick toggle
+toggle_muting = "Ctrl+Alt+M"
+"#;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ConfigFormat::from_path(Path::new("a/config.TOML")), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path(Path::new("a/config.json")), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let doc = ConfigFormat::Toml.parse(COMMENTED).unwrap();
+        assert_eq!(doc["excluded_apps"], json!(["spotify.exe", "discord.exe"]));
+        assert_eq!(doc["hotkeys"]["toggle_muting"], json!("Ctrl+Alt+M"));
+    }
+
+    #[test]
+    fn test_toml_error_location() {
+        let error = ConfigFormat::Toml.parse("muting_enabled = true\npoll_interval_ms = ").unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn test_render_keeps_comments() {
+        let mut doc = ConfigFormat::Toml.parse(COMMENTED).unwrap();
+        doc["muting_enabled"] = json!(false);
+        doc["excluded_apps"] = json!(["vlc.exe", "discord.exe", "spotify.exe"]);
+        doc["snoozed_until"] = Value::Null;
+
+        let text = ConfigFormat::Toml.render(&doc, Some(COMMENTED)).unwrap();
+
+        assert!(text.starts_with("# Work laptop\n"));
+        assert!(text.contains("muting_enabled = false # turned off in meetings"));
+        assert!(text.contains("\"spotify.exe\", # music"));
+        assert!(text.contains("# Quick toggle"));
+        assert!(text.find("discord.exe").unwrap() < text.find("vlc.exe").unwrap());
+        assert!(!text.contains("snoozed_until"));
+        assert_eq!(ConfigFormat::Toml.parse(&text).unwrap()["muting_enabled"], json!(false));
+    }
+
+    #[test]
+    fn test_render_new_toml() {
+        let doc = json!({"muting_enabled": true, "window_state": null});
+        let text = ConfigFormat::Toml.render(&doc, None).unwrap();
+        assert!(text.starts_with(TOML_HEADER));
+        assert!(text.contains("muting_enabled = true"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Config hot-reload module
+//! Watches the config directory with ReadDirectoryChangesW and merges
+//! external edits to config.json into the in-memory config.
+
+use crate::config::{Config, ConfigError};
+use crate::config_format::ConfigFormat;
+use crate::layers;
+use crate::migrations;
+use parking_lot::RwLock;
+use serde_json::{Map, Value};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, ReadDirectoryChangesW, FILE_FLAG_BACKUP_SEMANTICS, FILE_LIST_DIRECTORY,
+    FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_CHANGE_SIZE,
+    FILE_NOTIFY_INFORMATION, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+
+/// Editors often write a file in several steps; wait for them to finish
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the directory holding `path` and calls `on_change` whenever the
+/// file is written, created or renamed into place.
+///
+/// The watcher thread lives for the rest of the process.
+pub fn spawn_watcher<F>(path: PathBuf, on_change: F) -> std::io::Result<()>
+where
+    F: Fn() + Send + 'static,
+{
+    let dir = path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let file_name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+
+    thread::Builder::new()
+        .name("config-watcher".into())
+        .spawn(move || {
+            // On first run the directory may not have been written yet
+            if let Err(e) = fs::create_dir_all(&dir) {
+                log::warn!("Failed to create {:?}: {}", dir, e);
+            }
+            if let Err(e) = watch_directory(&dir, &file_name, on_change) {
+                log::warn!("Config watcher stopped: {}", e);
+            }
+        })?;
+    Ok(())
+}
+
+fn watch_directory<F: Fn()>(
+    dir: &Path,
+    file_name: &OsStr,
+    on_change: F,
+) -> windows::core::Result<()> {
+    let dir_wide: Vec<u16> = dir.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let wanted = file_name.to_string_lossy();
+
+    unsafe {
+        let handle = CreateFileW(
+            PCWSTR(dir_wide.as_ptr()),
+            FILE_LIST_DIRECTORY.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS,
+            None,
+        )?;
+        log::info!("Watching {:?} for config changes", dir);
+
+        // FILE_NOTIFY_INFORMATION records must be DWORD-aligned
+        let mut buffer = vec![0u32; 2048];
+        let result = loop {
+            let mut returned = 0u32;
+            if let Err(e) = ReadDirectoryChangesW(
+                handle,
+                buffer.as_mut_ptr() as *mut _,
+                (buffer.len() * 4) as u32,
+                false,
+                FILE_NOTIFY_CHANGE_LAST_WRITE
+                    | FILE_NOTIFY_CHANGE_FILE_NAME
+                    | FILE_NOTIFY_CHANGE_SIZE,
+                Some(&mut returned),
+                None,
+                None,
+            ) {
+                break Err(e);
+            }
+
+            // Zero bytes means the buffer overflowed; assume our file changed
+            let relevant = returned == 0 || notifications_mention(&buffer, &wanted);
+            if relevant {
+                thread::sleep(DEBOUNCE);
+                on_change();
+            }
+        };
+
+        let _ = CloseHandle(handle);
+        result
+    }
+}
+
+/// Walks a FILE_NOTIFY_INFORMATION chain looking for `wanted` (case-insensitive)
+unsafe fn notifications_mention(buffer: &[u32], wanted: &str) -> bool {
+    let base = buffer.as_ptr() as *const u8;
+    let mut offset = 0usize;
+
+    loop {
+        let info = &*(base.add(offset) as *const FILE_NOTIFY_INFORMATION);
+        let name = std::slice::from_raw_parts(
+            info.FileName.as_ptr(),
+            info.FileNameLength as usize / 2,
+        );
+        if String::from_utf16_lossy(name).eq_ignore_ascii_case(wanted) {
+            return true;
+        }
+
+        if info.NextEntryOffset == 0 {
+            return false;
+        }
+        offset += info.NextEntryOffset as usize;
+    }
+}
+
+/// Applies external edits of the config file on top of in-memory changes
+///
+/// Keeps the last version seen on disk as the merge base: fields edited on
+/// disk since then win, everything else keeps its in-memory value.
+pub struct ConfigReloader {
+    base: Value,
+}
+
+impl ConfigReloader {
+    /// Creates a reloader using the current file contents as the base
+    pub fn new() -> Self {
+        let base = read_document(&Config::config_path()).unwrap_or(Value::Null);
+        Self { base }
+    }
+
+    /// Re-reads the config file and merges it into `config`
+    /// Returns Ok(true) if the in-memory config changed.
+    pub fn reload(&mut self, config: &RwLock<Config>) -> Result<bool, ConfigError> {
+        let theirs = read_document(&Config::config_path())?;
+
+        // Validate the edit on its own before merging anything
+        serde_json::from_value::<Config>(theirs.clone()).map_err(ConfigError::other)?;
+
+        let ours = serde_json::to_value(&*config.read()).map_err(ConfigError::other)?;
+        let merged = merge(Some(&self.base), Some(&ours), Some(&theirs)).unwrap_or(Value::Null);
+        self.base = theirs;
+
+        if merged == ours {
+            return Ok(false);
+        }
+
+        let mut new_config: Config =
+            serde_json::from_value(merged).map_err(ConfigError::other)?;
+        new_config.normalize();
+        *config.write() = new_config;
+        Ok(true)
+    }
+}
+
+impl Default for ConfigReloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the file as a current-schema document with the policy and
+/// overrides applied, so it compares like for like with the in-memory config
+fn read_document(path: &Path) -> Result<Value, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(ConfigError::other)?;
+    let mut doc = ConfigFormat::from_path(path).parse(&contents)?;
+    migrations::migrate(&mut doc).map_err(ConfigError::other)?;
+
+    let layers = layers::current();
+    layers.remember_user_values(&doc);
+    layers.apply(&mut doc);
+    Ok(doc)
+}
+
+/// Three-way merge of JSON documents (`None` = key absent)
+///
+/// Objects merge key by key and arrays merge as sets (our app lists are
+/// sets); on a true conflict the on-disk edit wins as the latest intent.
+fn merge(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Option<Value> {
+    if theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+
+    match (base, ours, theirs) {
+        (Some(Value::Object(b)), Some(Value::Object(o)), Some(Value::Object(t))) => {
+            let mut merged = Map::new();
+            for key in o.keys().chain(t.keys()) {
+                if merged.contains_key(key) {
+                    continue;
+                }
+                if let Some(value) = merge(b.get(key), o.get(key), t.get(key)) {
+                    merged.insert(key.clone(), value);
+                }
+            }
+            Some(Value::Object(merged))
+        }
+        (Some(Value::Array(b)), Some(Value::Array(o)), Some(Value::Array(t))) => {
+            // Keep ours minus what the edit removed, then add what it introduced
+            let mut merged: Vec<Value> = o
+                .iter()
+                .filter(|v| !b.contains(v) || t.contains(v))
+                .cloned()
+                .collect();
+            for value in t {
+                if !b.contains(value) && !merged.contains(value) {
+                    merged.push(value.clone());
+                }
+            }
+            Some(Value::Array(merged))
+        }
+        _ => theirs.cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn merge3(base: Value, ours: Value, theirs: Value) -> Value {
+        merge(Some(&base), Some(&ours), Some(&theirs)).unwrap()
+    }
+
+    #[test]
+    fn test_merge_keeps_both_sides() {
+        let base = json!({"muting_enabled": true, "poll_interval_ms": 500});
+        let ours = json!({"muting_enabled": false, "poll_interval_ms": 500});
+        let theirs = json!({"muting_enabled": true, "poll_interval_ms": 250});
+
+        assert_eq!(
+            merge3(base, ours, theirs),
+            json!({"muting_enabled": false, "poll_interval_ms": 250})
+        );
+    }
+
+    #[test]
+    fn test_merge_sets() {
+        let base = json!({"excluded_apps": ["a.exe", "b.exe"]});
+        let ours = json!({"excluded_apps": ["a.exe", "b.exe", "tray.exe"]});
+        let theirs = json!({"excluded_apps": ["b.exe", "edited.exe"]});
+
+        assert_eq!(
+            merge3(base, ours, theirs),
+            json!({"excluded_apps": ["b.exe", "tray.exe", "edited.exe"]})
+        );
+    }
+
+    #[test]
+    fn test_merge_maps_and_removed_keys() {
+        let base = json!({"temporary_exemptions": {"a.exe": 1, "b.exe": 2}});
+        let ours = json!({"temporary_exemptions": {"a.exe": 1, "b.exe": 2, "c.exe": 3}});
+        let theirs = json!({"temporary_exemptions": {"b.exe": 2}});
+
+        assert_eq!(
+            merge3(base, ours, theirs),
+            json!({"temporary_exemptions": {"b.exe": 2, "c.exe": 3}})
+        );
+    }
+
+    #[test]
+    fn test_conflict_prefers_disk() {
+        let base = json!({"poll_interval_ms": 500});
+        let ours = json!({"poll_interval_ms": 300});
+        let theirs = json!({"poll_interval_ms": 700});
+        assert_eq!(merge3(base, ours, theirs), json!({"poll_interval_ms": 700}));
+    }
+}
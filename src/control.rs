@@ -0,0 +1,618 @@
+//! Remote control module
+//! Requests other processes can send to the running instance (see `ipc`),
+//! how the instance carries them out, and the `bg-muter ctl` client.
+
+use crate::clock;
+use crate::config::Config;
+use crate::events;
+use crate::instance;
+use crate::ipc;
+use crate::layers;
+use crate::muter::MuterEngine;
+use crate::startup;
+use crate::tray::TrayCommand;
+use crossbeam_channel::Sender;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A request to the running instance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    Toggle,
+    Enable,
+    Disable,
+    /// Adds an app to (or with `remove`, takes it off) the exclusion list
+    Exclude {
+        app: String,
+        #[serde(default)]
+        remove: bool,
+    },
+    /// Adds an app to (or with `remove`, takes it off) the always-muted list
+    AlwaysMute {
+        app: String,
+        #[serde(default)]
+        remove: bool,
+    },
+    ListSessions,
+    /// Snoozes muting for `seconds` (0 ends a snooze)
+    Snooze { seconds: u64 },
+    Explain { pid: u32 },
+    Reload,
+    /// Gets the effective settings
+    Config,
+    /// Changes one setting, given as its config file key and JSON value
+    Set { key: String, value: Value },
+    /// Turns the connection into a stream of events (see `events::Event`)
+    Subscribe,
+    /// Sent by a second launch with its command-line flags (see `instance`)
+    Activate { args: Vec<String> },
+}
+
+/// The answer to a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Machine-readable result
+    #[serde(default)]
+    pub data: Value,
+    /// The same result for people
+    #[serde(default)]
+    pub text: String,
+}
+
+impl Response {
+    pub fn success(data: impl Serialize, text: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            data: serde_json::to_value(data).unwrap_or(Value::Null),
+            text: text.into(),
+        }
+    }
+
+    pub fn failure(error: impl Into<String>) -> Self {
+        let error = error.into();
+        Self {
+            ok: false,
+            text: error.clone(),
+            error: Some(error),
+            data: Value::Null,
+        }
+    }
+}
+
+/// Muting state as reported by `status`
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub muting_enabled: bool,
+    pub snoozed_for_secs: Option<u64>,
+    pub dry_run: bool,
+    pub foreground_pid: Option<u32>,
+    pub foreground_app: Option<String>,
+    pub muted_apps: Vec<SessionInfo>,
+}
+
+/// An audio session as reported by `list-sessions`
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub pid: u32,
+    pub process_name: String,
+    pub display_name: String,
+    pub muted_by_us: bool,
+    pub active: bool,
+}
+
+/// The shared state requests act on
+#[derive(Clone)]
+pub struct ControlContext {
+    pub config: Arc<RwLock<Config>>,
+    pub engine: Arc<RwLock<MuterEngine>>,
+    pub muting_enabled: Arc<AtomicBool>,
+    /// For work that has to happen on the tray thread
+    pub tray: Sender<TrayCommand>,
+}
+
+impl ControlContext {
+    /// Carries out a request; the tray icon catches up on its next tick
+    pub fn execute(&self, request: &Request) -> Response {
+        match request {
+            Request::Status => self.status(),
+            Request::Toggle => {
+                let enabled = !self.muting_enabled.load(Ordering::SeqCst);
+                self.set_muting(enabled)
+            }
+            Request::Enable => self.set_muting(true),
+            Request::Disable => self.set_muting(false),
+            Request::Exclude { app, remove } => self.edit_list("excluded_apps", app, *remove),
+            Request::AlwaysMute { app, remove } => {
+                self.edit_list("always_muted_apps", app, *remove)
+            }
+            Request::ListSessions => self.list_sessions(),
+            Request::Snooze { seconds } => self.snooze(*seconds),
+            Request::Explain { pid } => {
+                let explanation = self.engine.read().explain(*pid);
+                let text = explanation.to_string();
+                Response::success(explanation, text)
+            }
+            Request::Reload => {
+                let _ = self.tray.try_send(TrayCommand::ReloadConfig);
+                Response::success(Value::Null, "Reloading the config file")
+            }
+            Request::Config => {
+                let config = serde_json::to_value(&*self.config.read()).unwrap_or_default();
+                let text = serde_json::to_string_pretty(&config).unwrap_or_default();
+                Response::success(config, text)
+            }
+            Request::Set { key, value } => self.set(key, value),
+            Request::Subscribe => Response::failure("subscribe needs a streaming connection"),
+            Request::Activate { args } => {
+                for command in args.iter().filter_map(|arg| instance::command_for_flag(arg)) {
+                    let _ = self.tray.try_send(command);
+                }
+                let text = if args.is_empty() {
+                    "Background Muter is already running".to_string()
+                } else {
+                    format!("Passed {} to the running instance", args.join(" "))
+                };
+                Response::success(args, text)
+            }
+        }
+    }
+
+    fn set(&self, key: &str, value: &Value) -> Response {
+        // Hotkeys belong to the tray thread, which picks them up from the file
+        if key == "hotkeys" {
+            return Response::failure("hotkeys can only be changed in the config file");
+        }
+        // Hooks run commands, so only someone who can edit the file may set them
+        if key == "hooks" {
+            return Response::failure("hooks can only be changed in the config file");
+        }
+        if let Err(e) = self.config.write().set_value(key, value.clone()) {
+            return Response::failure(e);
+        }
+
+        // The muting thread takes the config lock while holding the engine's,
+        // so the config guard must be gone before the engine is locked
+        let (muting_enabled, start_with_windows) = {
+            let config = self.config.read();
+            (config.muting_enabled, config.start_with_windows)
+        };
+        match key {
+            "muting_enabled" => {
+                events::store_muting_flag(&self.muting_enabled, muting_enabled);
+                if !muting_enabled {
+                    self.engine.write().unmute_all();
+                }
+            }
+            "start_with_windows" => {
+                if let Err(e) = startup::apply_startup_setting(start_with_windows) {
+                    log::warn!("Failed to apply startup setting: {}", e);
+                }
+            }
+            _ => {}
+        }
+        self.engine.write().request_refresh();
+
+        Response::success(serde_json::json!({ key: value }), format!("Set {} to {}", key, value))
+    }
+
+    fn status(&self) -> Response {
+        let (snoozed_for_secs, dry_run) = {
+            let config = self.config.read();
+            (config.snooze_remaining().map(|d| d.as_secs()), config.dry_run)
+        };
+        let engine = self.engine.read();
+        let foreground = engine.foreground_app();
+        let muted_apps = sessions(&engine).into_iter().filter(|s| s.muted_by_us).collect();
+
+        let status = Status {
+            muting_enabled: self.muting_enabled.load(Ordering::SeqCst),
+            snoozed_for_secs,
+            dry_run,
+            foreground_pid: foreground.as_ref().map(|(pid, _)| *pid),
+            foreground_app: foreground.map(|(_, name)| name),
+            muted_apps,
+        };
+        let text = status.to_string();
+        Response::success(status, text)
+    }
+
+    fn set_muting(&self, enabled: bool) -> Response {
+        if !Config::is_editable("muting_enabled") {
+            return Response::failure("muting_enabled is locked by policy or an override");
+        }
+
+        {
+            let mut config = self.config.write();
+            if enabled {
+                // Turning muting on also ends a snooze, as in the tray
+                config.snoozed_until = None;
+            }
+            config.set_muting(enabled);
+        }
+        events::store_muting_flag(&self.muting_enabled, enabled);
+
+        let mut engine = self.engine.write();
+        if !enabled {
+            engine.unmute_all();
+        }
+        engine.request_refresh();
+
+        let text = if enabled { "Muting enabled" } else { "Muting disabled" };
+        Response::success(serde_json::json!({ "muting_enabled": enabled }), text)
+    }
+
+    fn edit_list(&self, key: &str, app: &str, remove: bool) -> Response {
+        let app = app.trim().to_lowercase();
+        if app.is_empty() {
+            return Response::failure("no app name given");
+        }
+        let allowed = if remove {
+            Config::is_list_entry_removable(key, &app)
+        } else {
+            Config::is_editable(key)
+        };
+        if !allowed {
+            return Response::failure(format!("{} is locked by policy for {}", key, app));
+        }
+
+        {
+            let mut config = self.config.write();
+            match (key, remove) {
+                ("excluded_apps", false) => config.add_excluded_app(&app),
+                ("excluded_apps", true) => config.remove_excluded_app(&app),
+                (_, false) => config.add_always_muted_app(&app),
+                (_, true) => config.remove_always_muted_app(&app),
+            }
+        }
+        self.engine.write().request_refresh();
+
+        let text = if remove {
+            format!("Removed {} from {}", app, key)
+        } else {
+            format!("Added {} to {}", app, key)
+        };
+        Response::success(serde_json::json!({ "list": key, "app": app, "removed": remove }), text)
+    }
+
+    fn list_sessions(&self) -> Response {
+        let sessions = sessions(&self.engine.read());
+        let text = if sessions.is_empty() {
+            "No audio sessions".to_string()
+        } else {
+            sessions.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+        };
+        Response::success(sessions, text)
+    }
+
+    fn snooze(&self, seconds: u64) -> Response {
+        if seconds > MAX_SNOOZE_SECS {
+            return Response::failure("a snooze can last at most a year");
+        }
+        {
+            let mut config = self.config.write();
+            if seconds == 0 {
+                config.cancel_snooze();
+            } else {
+                config.snooze_for(Duration::from_secs(seconds));
+            }
+        }
+        self.engine.write().request_refresh();
+
+        let text = if seconds == 0 {
+            "Snooze cancelled".to_string()
+        } else {
+            format!("Muting snoozed for {}", format_duration(seconds))
+        };
+        Response::success(serde_json::json!({ "snoozed_for_secs": seconds }), text)
+    }
+}
+
+fn sessions(engine: &MuterEngine) -> Vec<SessionInfo> {
+    let mut sessions: Vec<SessionInfo> = engine
+        .get_app_states()
+        .into_iter()
+        .map(|state| SessionInfo {
+            pid: state.pid,
+            process_name: state.process_name,
+            display_name: state.display_name,
+            muted_by_us: state.is_muted_by_us,
+            active: state.is_active,
+        })
+        .collect();
+    sessions.sort_by(|a, b| a.process_name.cmp(&b.process_name).then(a.pid.cmp(&b.pid)));
+    sessions
+}
+
+/// Formats seconds as e.g. "1h 30m"
+fn format_duration(seconds: u64) -> String {
+    let minutes = seconds.div_ceil(60);
+    let (hours, minutes) = (minutes / 60, minutes % 60);
+    match (hours, minutes) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Muting: {}", if self.muting_enabled { "on" } else { "off" })?;
+        if let Some(secs) = self.snoozed_for_secs {
+            write!(f, " (snoozed for {})", format_duration(secs))?;
+        }
+        if self.dry_run {
+            write!(f, " [dry run]")?;
+        }
+        writeln!(f)?;
+
+        match (&self.foreground_app, self.foreground_pid) {
+            (Some(app), Some(pid)) => writeln!(f, "Foreground: {} (PID {})", app, pid)?,
+            _ => writeln!(f, "Foreground: unknown")?,
+        }
+
+        if self.muted_apps.is_empty() {
+            write!(f, "Muted: nothing")
+        } else {
+            let muted: Vec<String> = self
+                .muted_apps
+                .iter()
+                .map(|s| format!("{} (PID {})", s.process_name, s.pid))
+                .collect();
+            write!(f, "Muted: {}", muted.join(", "))
+        }
+    }
+}
+
+impl std::fmt::Display for SessionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match (self.muted_by_us, self.active) {
+            (true, _) => "muted",
+            (false, true) => "audible",
+            (false, false) => "inactive",
+        };
+        write!(f, "{:>7}  {:<28} {}", self.pid, self.process_name, state)
+    }
+}
+
+/// Longest snooze accepted over IPC
+const MAX_SNOOZE_SECS: u64 = 365 * 24 * 60 * 60;
+
+const CTL_USAGE: &str = "Usage: bg-muter ctl [--json] <command>
+
+Commands:
+  status                     Muting state, foreground app and muted apps
+  toggle | enable | disable  Turn muting on or off
+  exclude [--remove] <app>   Never mute an app (or stop excluding it)
+  always-mute [--remove] <app>
+                             Always mute an app (or stop)
+  list-sessions              Audio sessions and whether they are muted
+  snooze <duration>|off      Keep everything audible, e.g. 30m, 1h30m
+  explain <pid>              Why an app is muted or audible
+  reload                     Re-read the config file
+  config                     Print the effective settings
+  set <key>=<value>          Change a setting (value is JSON or plain text)
+  watch                      Print mutes, unmutes, focus changes and toggles live";
+
+/// Parses `ctl` arguments into a request and whether to print JSON
+pub fn parse_ctl_args(args: &[String]) -> Result<(Request, bool), String> {
+    let json = args.iter().any(|a| a == "--json");
+    let remove = args.iter().any(|a| a == "--remove");
+    let mut words = args.iter().filter(|a| *a != "--json" && *a != "--remove");
+
+    let command = words.next().ok_or("no command given")?;
+    let mut argument = |what: &str| {
+        words
+            .next()
+            .cloned()
+            .ok_or_else(|| format!("{} needs {}", command, what))
+    };
+
+    let request = match command.as_str() {
+        "status" => Request::Status,
+        "toggle" => Request::Toggle,
+        "enable" => Request::Enable,
+        "disable" => Request::Disable,
+        "exclude" => Request::Exclude { app: argument("an app name")?, remove },
+        "always-mute" => Request::AlwaysMute { app: argument("an app name")?, remove },
+        "list-sessions" => Request::ListSessions,
+        "snooze" => {
+            let duration = argument("a duration such as 30m")?;
+            let seconds = match duration.as_str() {
+                "off" | "cancel" | "0" => 0,
+                _ => clock::parse_duration(&duration)
+                    .ok_or_else(|| format!("invalid duration {:?}", duration))?
+                    .as_secs(),
+            };
+            Request::Snooze { seconds }
+        }
+        "explain" => {
+            let pid = argument("a PID")?;
+            let pid = pid.parse().map_err(|_| format!("invalid PID {:?}", pid))?;
+            Request::Explain { pid }
+        }
+        "reload" => Request::Reload,
+        "config" => Request::Config,
+        "set" => {
+            let setting = argument("<key>=<value>")?;
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected <key>=<value>, got {:?}", setting))?;
+            Request::Set {
+                key: key.trim().to_string(),
+                value: layers::parse_override_value(value.trim()),
+            }
+        }
+        "watch" => Request::Subscribe,
+        other => return Err(format!("unknown command {:?}", other)),
+    };
+    Ok((request, json))
+}
+
+/// Runs `bg-muter ctl ...`, returning the process exit code
+///
+/// 0 on success, 1 if the instance refused, 2 for bad arguments and 3 if
+/// no instance is running.
+pub fn run_ctl(args: &[String]) -> i32 {
+    let (request, json) = match parse_ctl_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, CTL_USAGE);
+            return 2;
+        }
+    };
+
+    if request == Request::Subscribe {
+        return watch(json);
+    }
+
+    let response = match ipc::send_request(&request) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Background Muter is not running ({})", e);
+            return 3;
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response).unwrap_or_default());
+    } else if response.ok {
+        println!("{}", response.text);
+    } else {
+        eprintln!("Error: {}", response.text);
+    }
+    if response.ok {
+        0
+    } else {
+        1
+    }
+}
+
+/// Prints events until the instance exits or the user presses Ctrl+C
+fn watch(json: bool) -> i32 {
+    let events = match ipc::Client::connect(&ipc::endpoint()).and_then(|c| c.subscribe()) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Background Muter is not running ({})", e);
+            return 3;
+        }
+    };
+
+    for event in events {
+        match event {
+            Ok(event) if json => println!("{}", serde_json::to_string(&event).unwrap_or_default()),
+            Ok(event) => println!("{}", event),
+            Err(e) => {
+                eprintln!("Connection lost ({})", e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_ctl_args() {
+        assert_eq!(parse_ctl_args(&args("status")), Ok((Request::Status, false)));
+        assert_eq!(parse_ctl_args(&args("--json toggle")), Ok((Request::Toggle, true)));
+        assert_eq!(
+            parse_ctl_args(&args("exclude --remove Spotify.exe")),
+            Ok((Request::Exclude { app: "Spotify.exe".into(), remove: true }, false))
+        );
+        assert_eq!(
+            parse_ctl_args(&args("snooze 1h30m")),
+            Ok((Request::Snooze { seconds: 5400 }, false))
+        );
+        assert_eq!(
+            parse_ctl_args(&args("snooze off")),
+            Ok((Request::Snooze { seconds: 0 }, false))
+        );
+        assert_eq!(
+            parse_ctl_args(&args("explain 42 --json")),
+            Ok((Request::Explain { pid: 42 }, true))
+        );
+
+        assert_eq!(
+            parse_ctl_args(&args("set poll_interval_ms=250")),
+            Ok((Request::Set { key: "poll_interval_ms".into(), value: 250.into() }, false))
+        );
+        assert_eq!(parse_ctl_args(&args("watch --json")), Ok((Request::Subscribe, true)));
+
+        assert!(parse_ctl_args(&args("")).is_err());
+        assert!(parse_ctl_args(&args("set dry_run")).is_err());
+        assert!(parse_ctl_args(&args("exclude")).is_err());
+        assert!(parse_ctl_args(&args("explain chrome")).is_err());
+        assert!(parse_ctl_args(&args("snooze soon")).is_err());
+        assert!(parse_ctl_args(&args("dance")).is_err());
+    }
+
+    #[test]
+    fn test_request_wire_format() {
+        let request = Request::AlwaysMute { app: "ads.exe".into(), remove: false };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"cmd": "always-mute", "app": "ads.exe", "remove": false})
+        );
+
+        let parsed: Request =
+            serde_json::from_str(r#"{"cmd": "exclude", "app": "a.exe"}"#).unwrap();
+        assert_eq!(parsed, Request::Exclude { app: "a.exe".into(), remove: false });
+
+        let activate = Request::Activate { args: vec!["--settings".into()] };
+        assert_eq!(
+            serde_json::to_value(&activate).unwrap(),
+            serde_json::json!({"cmd": "activate", "args": ["--settings"]})
+        );
+    }
+
+    #[test]
+    fn test_status_text() {
+        let status = Status {
+            muting_enabled: true,
+            snoozed_for_secs: Some(5400),
+            dry_run: false,
+            foreground_pid: Some(7),
+            foreground_app: Some("chrome.exe".into()),
+            muted_apps: vec![SessionInfo {
+                pid: 9,
+                process_name: "spotify.exe".into(),
+                display_name: "Spotify".into(),
+                muted_by_us: true,
+                active: true,
+            }],
+        };
+        assert_eq!(
+            status.to_string(),
+            "Muting: on (snoozed for 1h 30m)\n\
+             Foreground: chrome.exe (PID 7)\n\
+             Muted: spotify.exe (PID 9)"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(59), "1m");
+        assert_eq!(format_duration(3599), "1h");
+        assert_eq!(format_duration(3601), "1h 1m");
+        assert_eq!(format_duration(7199), "2h");
+        assert_eq!(format_duration(MAX_SNOOZE_SECS), "8760h");
+    }
+}
@@ -0,0 +1,1154 @@
+//! D-Bus module (Linux only)
+//! Publishes `org.bgmuter.Muter1` on the session bus so desktop extensions
+//! and scripts can integrate: the Enabled, ActiveProfile and MutedApps
+//! properties, the Toggle, Exclude, Snooze and Explain methods and the
+//! AppMuted, AppUnmuted and ForegroundChanged signals.
+//! Calls go through the same control requests as `ipc`. The wire protocol is
+//! small enough to speak directly over the bus socket.
+
+use crate::control::{ControlContext, Request, Response};
+use crate::events::{self, Event};
+use crate::ipc::Handler;
+use parking_lot::Mutex;
+use serde_json::Value as Json;
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::sync::Arc;
+use std::thread;
+
+/// The well-known name the service owns on the session bus
+pub const BUS_NAME: &str = "org.bgmuter.Muter1";
+pub const OBJECT_PATH: &str = "/org/bgmuter/Muter1";
+pub const INTERFACE: &str = "org.bgmuter.Muter1";
+
+const BUS_DAEMON: &str = "org.freedesktop.DBus";
+const BUS_DAEMON_PATH: &str = "/org/freedesktop/DBus";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+/// Error name for control requests that were refused
+const ERROR_FAILED: &str = "org.bgmuter.Muter1.Error.Failed";
+
+/// Largest message accepted (the bus allows more; ours are tiny)
+const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// Largest line accepted while authenticating
+const MAX_AUTH_LINE: u64 = 1024;
+
+/// `RequestName` flag: fail instead of queueing behind another owner
+const NAME_FLAG_DO_NOT_QUEUE: u32 = 0x4;
+const NAME_REPLY_PRIMARY_OWNER: u32 = 1;
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC
+ "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.bgmuter.Muter1">
+    <property name="Enabled" type="b" access="read"/>
+    <property name="ActiveProfile" type="s" access="read"/>
+    <property name="MutedApps" type="as" access="read"/>
+    <method name="Toggle">
+      <arg name="enabled" type="b" direction="out"/>
+    </method>
+    <method name="Exclude">
+      <arg name="app" type="s" direction="in"/>
+      <arg name="remove" type="b" direction="in"/>
+    </method>
+    <method name="Snooze">
+      <arg name="seconds" type="t" direction="in"/>
+    </method>
+    <method name="Explain">
+      <arg name="pid" type="u" direction="in"/>
+      <arg name="explanation" type="s" direction="out"/>
+    </method>
+    <signal name="AppMuted">
+      <arg name="app" type="s"/>
+      <arg name="pid" type="u"/>
+      <arg name="reason" type="s"/>
+    </signal>
+    <signal name="AppUnmuted">
+      <arg name="app" type="s"/>
+      <arg name="pid" type="u"/>
+      <arg name="reason" type="s"/>
+    </signal>
+    <signal name="ForegroundChanged">
+      <arg name="app" type="s"/>
+      <arg name="pid" type="u"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="property" type="s" direction="in"/>
+      <arg name="value" type="v" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="properties" type="a{sv}" direction="out"/>
+    </method>
+    <method name="Set">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="property" type="s" direction="in"/>
+      <arg name="value" type="v" direction="in"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg name="interface" type="s"/>
+      <arg name="changed" type="a{sv}"/>
+      <arg name="invalidated" type="as"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+/// Starts the service on the session bus
+pub fn spawn(context: ControlContext) -> io::Result<()> {
+    spawn_at(&session_bus_address()?, Arc::new(move |request| context.execute(request)))
+}
+
+/// Starts the service on the bus at `address`; fails if another process
+/// already owns the name
+pub fn spawn_at(address: &str, handler: Handler) -> io::Result<()> {
+    let mut connection = Connection::connect(address)?;
+    let reply = connection.call(Message::method_call(
+        BUS_DAEMON,
+        BUS_DAEMON_PATH,
+        BUS_DAEMON,
+        "RequestName",
+        vec![Value::Str(BUS_NAME.to_string()), Value::U32(NAME_FLAG_DO_NOT_QUEUE)],
+    ))?;
+    if reply.body.first() != Some(&Value::U32(NAME_REPLY_PRIMARY_OWNER)) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is already owned on the bus", BUS_NAME),
+        ));
+    }
+    log::info!("Serving {} on the session bus", BUS_NAME);
+
+    // Subscribed before returning, so no event after startup goes unsignalled
+    let events = events::subscribe();
+    let outgoing = connection.outgoing.clone();
+    thread::Builder::new()
+        .name("dbus-signals".to_string())
+        .spawn(move || {
+            for event in events {
+                if let Err(e) = outgoing.lock().send(&mut signal_for(&event)) {
+                    log::debug!("D-Bus signal thread ended: {}", e);
+                    return;
+                }
+            }
+        })?;
+
+    thread::Builder::new()
+        .name("dbus".to_string())
+        .spawn(move || loop {
+            let call = match connection.read_message() {
+                Ok(message) if message.kind == MessageKind::MethodCall => message,
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("Lost the D-Bus connection: {}", e);
+                    return;
+                }
+            };
+            let mut reply = dispatch(&call, &handler);
+            if call.flags & FLAG_NO_REPLY_EXPECTED != 0 {
+                continue;
+            }
+            if let Err(e) = connection.outgoing.lock().send(&mut reply) {
+                log::warn!("Failed to answer a D-Bus call: {}", e);
+            }
+        })?;
+    Ok(())
+}
+
+/// Gets the session bus address from the environment, falling back to the
+/// usual socket in the runtime directory
+fn session_bus_address() -> io::Result<String> {
+    match std::env::var("DBUS_SESSION_BUS_ADDRESS") {
+        Ok(address) if !address.is_empty() => return Ok(address),
+        _ => {}
+    }
+    let runtime_dir = dirs::runtime_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no session bus address"))?;
+    Ok(format!("unix:path={}", runtime_dir.join("bus").display()))
+}
+
+/// Answers a method call on our object
+fn dispatch(call: &Message, handler: &Handler) -> Message {
+    let path = call.path.as_deref().unwrap_or_default();
+    let member = call.member.as_deref().unwrap_or_default();
+    if path != OBJECT_PATH {
+        // The parent paths only exist so introspection can find us
+        if call.interface.as_deref() == Some(INTROSPECTABLE) && member == "Introspect" {
+            if let Some(xml) = parent_node(path) {
+                return call.reply(vec![Value::Str(xml)]);
+            }
+        }
+        let text = format!("No object {}", path);
+        return call.error("org.freedesktop.DBus.Error.UnknownObject", text);
+    }
+
+    let result = match (call.interface.as_deref().unwrap_or(INTERFACE), member) {
+        (INTERFACE, method) => call_method(method, call, handler),
+        (PROPERTIES, "Get") => match call.body.as_slice() {
+            [Value::Str(interface), Value::Str(name)] if interface == INTERFACE => {
+                property(name, handler).map(|value| vec![Value::Variant(Box::new(value))])
+            }
+            [Value::Str(_), Value::Str(name)] => Err(unknown_property(name)),
+            _ => Err(invalid_args("ss")),
+        },
+        (PROPERTIES, "GetAll") => match call.body.as_slice() {
+            [Value::Str(interface)] if interface == INTERFACE => all_properties(handler),
+            [Value::Str(_)] => Ok(vec![Value::Array("{sv}".to_string(), Vec::new())]),
+            _ => Err(invalid_args("s")),
+        },
+        (PROPERTIES, "Set") => Err((
+            "org.freedesktop.DBus.Error.PropertyReadOnly",
+            "The properties are read-only; use the methods".to_string(),
+        )),
+        (INTROSPECTABLE, "Introspect") => Ok(vec![Value::Str(INTROSPECTION.to_string())]),
+        (PEER, "Ping") => Ok(Vec::new()),
+        (interface, member) => Err(unknown_method(interface, member)),
+    };
+    match result {
+        Ok(body) => call.reply(body),
+        Err((name, text)) => call.error(name, text),
+    }
+}
+
+/// An error reply: its name and message
+type CallError = (&'static str, String);
+
+fn invalid_args(expected: &str) -> CallError {
+    (
+        "org.freedesktop.DBus.Error.InvalidArgs",
+        format!("Expected arguments of type \"{}\"", expected),
+    )
+}
+
+fn unknown_method(interface: &str, member: &str) -> CallError {
+    (
+        "org.freedesktop.DBus.Error.UnknownMethod",
+        format!("No method {}.{}", interface, member),
+    )
+}
+
+fn unknown_property(name: &str) -> CallError {
+    ("org.freedesktop.DBus.Error.UnknownProperty", format!("No property {}", name))
+}
+
+/// Carries out one of our methods as a control request
+fn call_method(member: &str, call: &Message, handler: &Handler) -> Result<Vec<Value>, CallError> {
+    match (member, call.body.as_slice()) {
+        ("Toggle", []) => {
+            let data = execute(handler, &Request::Toggle)?.data;
+            Ok(vec![Value::Bool(data["muting_enabled"].as_bool().unwrap_or_default())])
+        }
+        ("Exclude", [Value::Str(app), Value::Bool(remove)]) => {
+            let request = Request::Exclude { app: app.clone(), remove: *remove };
+            execute(handler, &request).map(|_| Vec::new())
+        }
+        ("Snooze", [Value::U64(seconds)]) => {
+            execute(handler, &Request::Snooze { seconds: *seconds }).map(|_| Vec::new())
+        }
+        ("Explain", [Value::U32(pid)]) => {
+            let response = execute(handler, &Request::Explain { pid: *pid })?;
+            Ok(vec![Value::Str(response.text)])
+        }
+        ("Toggle", _) => Err(invalid_args("")),
+        ("Exclude", _) => Err(invalid_args("sb")),
+        ("Snooze", _) => Err(invalid_args("t")),
+        ("Explain", _) => Err(invalid_args("u")),
+        _ => Err(unknown_method(INTERFACE, member)),
+    }
+}
+
+/// Runs a control request, turning a refusal into an error reply
+fn execute(handler: &Handler, request: &Request) -> Result<Response, CallError> {
+    let response = handler(request);
+    if response.ok {
+        Ok(response)
+    } else {
+        Err((ERROR_FAILED, response.text))
+    }
+}
+
+fn property(name: &str, handler: &Handler) -> Result<Value, CallError> {
+    match name {
+        "Enabled" => Ok(enabled(&execute(handler, &Request::Status)?.data)),
+        "MutedApps" => Ok(muted_apps(&execute(handler, &Request::Status)?.data)),
+        "ActiveProfile" => Ok(active_profile(&execute(handler, &Request::Config)?.data)),
+        _ => Err(unknown_property(name)),
+    }
+}
+
+fn all_properties(handler: &Handler) -> Result<Vec<Value>, CallError> {
+    let status = execute(handler, &Request::Status)?.data;
+    let config = execute(handler, &Request::Config)?.data;
+    let entries = [
+        ("Enabled", enabled(&status)),
+        ("ActiveProfile", active_profile(&config)),
+        ("MutedApps", muted_apps(&status)),
+    ];
+    Ok(vec![Value::Array("{sv}".to_string(), entries.into_iter().map(dict_entry).collect())])
+}
+
+fn dict_entry((name, value): (&str, Value)) -> Value {
+    let value = Value::Variant(Box::new(value));
+    Value::DictEntry(Box::new(Value::Str(name.to_string())), Box::new(value))
+}
+
+fn enabled(status: &Json) -> Value {
+    Value::Bool(status["muting_enabled"].as_bool().unwrap_or_default())
+}
+
+/// The app names we muted, sorted and without duplicates
+fn muted_apps(status: &Json) -> Value {
+    let mut apps: Vec<String> = status["muted_apps"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|session| session["process_name"].as_str().map(str::to_string))
+        .collect();
+    apps.sort();
+    apps.dedup();
+    Value::Array("s".to_string(), apps.into_iter().map(Value::Str).collect())
+}
+
+/// The audibility policy in effect; the app has no other kind of profile
+fn active_profile(config: &Json) -> Value {
+    Value::Str(config["audibility_policy"].as_str().unwrap_or("focus").to_string())
+}
+
+/// Introspection data for a path above our object, e.g. `/org/bgmuter`
+fn parent_node(path: &str) -> Option<String> {
+    let prefix = if path == "/" { path.to_string() } else { format!("{}/", path) };
+    let child = OBJECT_PATH.strip_prefix(&prefix)?.split('/').next()?;
+    Some(format!("<node>\n  <node name=\"{}\"/>\n</node>\n", child))
+}
+
+/// The signal sent for an engine event
+fn signal_for(event: &Event) -> Message {
+    match event {
+        Event::AppMuted(audit) | Event::AppUnmuted(audit) => Message::signal(
+            OBJECT_PATH,
+            INTERFACE,
+            if matches!(event, Event::AppMuted(_)) { "AppMuted" } else { "AppUnmuted" },
+            vec![
+                Value::Str(audit.app.clone()),
+                Value::U32(audit.pid),
+                Value::Str(audit.reason.as_str().to_string()),
+            ],
+        ),
+        Event::ForegroundChanged(change) => Message::signal(
+            OBJECT_PATH,
+            INTERFACE,
+            "ForegroundChanged",
+            vec![
+                Value::Str(change.to_app.clone().unwrap_or_default()),
+                Value::U32(change.to_pid.unwrap_or_default()),
+            ],
+        ),
+        Event::MutingToggled { enabled } => Message::signal(
+            OBJECT_PATH,
+            PROPERTIES,
+            "PropertiesChanged",
+            vec![
+                Value::Str(INTERFACE.to_string()),
+                Value::Array(
+                    "{sv}".to_string(),
+                    vec![dict_entry(("Enabled", Value::Bool(*enabled)))],
+                ),
+                Value::Array("s".to_string(), Vec::new()),
+            ],
+        ),
+    }
+}
+
+/// A connection to a message bus, authenticated and registered with `Hello`
+struct Connection {
+    reader: BufReader<UnixStream>,
+    /// Shared with the signal thread
+    outgoing: Arc<Mutex<Outgoing>>,
+}
+
+/// The sending half of a connection, which numbers the messages
+struct Outgoing {
+    stream: UnixStream,
+    last_serial: u32,
+}
+
+impl Outgoing {
+    /// Sends a message, returning the serial it was given
+    fn send(&mut self, message: &mut Message) -> io::Result<u32> {
+        self.last_serial = self.last_serial.wrapping_add(1).max(1);
+        message.serial = self.last_serial;
+        self.stream.write_all(&message.encode())?;
+        Ok(message.serial)
+    }
+}
+
+impl Connection {
+    fn connect(address: &str) -> io::Result<Self> {
+        let stream = connect_socket(address)?;
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            outgoing: Arc::new(Mutex::new(Outgoing { stream, last_serial: 0 })),
+        };
+        connection.authenticate()?;
+        let hello = Message::method_call(BUS_DAEMON, BUS_DAEMON_PATH, BUS_DAEMON, "Hello", vec![]);
+        connection.call(hello)?;
+        Ok(connection)
+    }
+
+    /// Logs in with the EXTERNAL mechanism: the bus checks our uid against
+    /// the socket's credentials
+    fn authenticate(&mut self) -> io::Result<()> {
+        let uid = std::fs::metadata("/proc/self")?.uid();
+        let hex_uid: String = uid.to_string().bytes().map(|b| format!("{:02x}", b)).collect();
+        let mut outgoing = self.outgoing.lock();
+        outgoing.stream.write_all(format!("\0AUTH EXTERNAL {}\r\n", hex_uid).as_bytes())?;
+
+        let mut line = String::new();
+        (&mut self.reader).take(MAX_AUTH_LINE).read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("bus refused authentication: {}", line.trim_end()),
+            ));
+        }
+        outgoing.stream.write_all(b"BEGIN\r\n")
+    }
+
+    /// Sends a method call and waits for its reply, skipping anything else
+    /// that arrives meanwhile
+    fn call(&mut self, mut message: Message) -> io::Result<Message> {
+        let serial = self.outgoing.lock().send(&mut message)?;
+        loop {
+            let reply = self.read_message()?;
+            if reply.reply_serial != Some(serial) {
+                continue;
+            }
+            return match reply.kind {
+                MessageKind::Error => {
+                    let text = match reply.body.first() {
+                        Some(Value::Str(text)) => text.as_str(),
+                        _ => "",
+                    };
+                    let name = reply.error_name.as_deref().unwrap_or_default();
+                    Err(io::Error::other(format!("{}: {}", name, text)))
+                }
+                _ => Ok(reply),
+            };
+        }
+    }
+
+    fn read_message(&mut self) -> io::Result<Message> {
+        let mut fixed = [0u8; 16];
+        self.reader.read_exact(&mut fixed)?;
+        let big_endian = match fixed[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid_data("unknown byte order")),
+        };
+        let number = |at: usize| {
+            let bytes = [fixed[at], fixed[at + 1], fixed[at + 2], fixed[at + 3]];
+            if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+        };
+        let (body_len, fields_len) = (number(4) as usize, number(12) as usize);
+        let header_len = align_up(16 + fields_len, 8);
+        if header_len + body_len > MAX_MESSAGE_BYTES {
+            return Err(invalid_data("message too large"));
+        }
+
+        let mut bytes = fixed.to_vec();
+        bytes.resize(header_len + body_len, 0);
+        self.reader.read_exact(&mut bytes[16..])?;
+        Message::decode(&bytes, big_endian)
+    }
+}
+
+/// Connects to the first Unix socket listed in a bus address such as
+/// `unix:path=/run/user/1000/bus` or `unix:abstract=/tmp/dbus-x,guid=...`
+fn connect_socket(address: &str) -> io::Result<UnixStream> {
+    for entry in address.split(';') {
+        let Some(params) = entry.strip_prefix("unix:") else {
+            continue;
+        };
+        for (key, value) in params.split(',').filter_map(|param| param.split_once('=')) {
+            let value = unescape_address(value)?;
+            match key {
+                "path" => return UnixStream::connect(OsStr::from_bytes(&value)),
+                "abstract" => {
+                    return UnixStream::connect_addr(&SocketAddr::from_abstract_name(&value)?)
+                }
+                _ => {}
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("no usable Unix socket in bus address {}", address),
+    ))
+}
+
+/// Decodes the `%xx` escapes of a bus address value
+fn unescape_address(value: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+            let decoded = hex.and_then(|h| u8::from_str_radix(h, 16).ok());
+            bytes.push(decoded.ok_or_else(|| invalid_data("bad escape in bus address"))?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn align_up(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+/// `MessageKind::MethodCall` flag: the caller doesn't want an answer
+const FLAG_NO_REPLY_EXPECTED: u8 = 0x1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageKind {
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+/// A D-Bus message with the header fields we use
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    kind: MessageKind,
+    flags: u8,
+    serial: u32,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    reply_serial: Option<u32>,
+    destination: Option<String>,
+    sender: Option<String>,
+    body: Vec<Value>,
+}
+
+impl Message {
+    fn new(kind: MessageKind, body: Vec<Value>) -> Self {
+        Self {
+            kind,
+            flags: 0,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body,
+        }
+    }
+
+    fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Self {
+        Self {
+            destination: Some(destination.to_string()),
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            ..Self::new(MessageKind::MethodCall, body)
+        }
+    }
+
+    fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            ..Self::new(MessageKind::Signal, body)
+        }
+    }
+
+    /// The successful answer to this call
+    fn reply(&self, body: Vec<Value>) -> Self {
+        Self {
+            reply_serial: Some(self.serial),
+            destination: self.sender.clone(),
+            ..Self::new(MessageKind::MethodReturn, body)
+        }
+    }
+
+    /// The error answer to this call
+    fn error(&self, name: &str, text: String) -> Self {
+        Self {
+            kind: MessageKind::Error,
+            error_name: Some(name.to_string()),
+            ..self.reply(vec![Value::Str(text)])
+        }
+    }
+
+    fn signature(&self) -> String {
+        self.body.iter().map(Value::signature).collect()
+    }
+
+    /// Encodes the message in little-endian byte order
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Encoder::default();
+        for value in &self.body {
+            body.put(value);
+        }
+
+        let strings = [
+            (1, self.path.as_ref().map(|p| Value::Path(p.clone()))),
+            (2, self.interface.clone().map(Value::Str)),
+            (3, self.member.clone().map(Value::Str)),
+            (4, self.error_name.clone().map(Value::Str)),
+            (6, self.destination.clone().map(Value::Str)),
+            (7, self.sender.clone().map(Value::Str)),
+        ];
+        let mut fields: Vec<Value> = strings
+            .into_iter()
+            .filter_map(|(code, value)| value.map(|value| field(code, value)))
+            .collect();
+        if let Some(serial) = self.reply_serial {
+            fields.push(field(5, Value::U32(serial)));
+        }
+        if !self.body.is_empty() {
+            fields.push(field(8, Value::Signature(self.signature())));
+        }
+
+        let mut header = Encoder::default();
+        header.bytes.extend_from_slice(&[b'l', self.kind as u8, self.flags, 1]);
+        header.put(&Value::U32(body.bytes.len() as u32));
+        header.put(&Value::U32(self.serial));
+        header.put(&Value::Array("(yv)".to_string(), fields));
+        header.align(8);
+        header.bytes.extend_from_slice(&body.bytes);
+        header.bytes
+    }
+
+    /// Decodes a whole message; `bytes` holds the padded header and the body
+    fn decode(bytes: &[u8], big_endian: bool) -> io::Result<Self> {
+        let kind = match bytes[1] {
+            1 => MessageKind::MethodCall,
+            2 => MessageKind::MethodReturn,
+            3 => MessageKind::Error,
+            4 => MessageKind::Signal,
+            _ => return Err(invalid_data("unknown message type")),
+        };
+        let mut header = Decoder { bytes, pos: 12, big_endian };
+        let Value::Array(_, fields) = header.get("a(yv)")? else {
+            unreachable!("an array signature decodes to an array");
+        };
+        header.align(8)?;
+        let body_start = header.pos;
+
+        let mut message = Self::new(kind, Vec::new());
+        message.flags = bytes[2];
+        message.serial = Decoder { bytes, pos: 8, big_endian }.u32()?;
+        let mut signature = String::new();
+        for field in fields {
+            let Value::Struct(parts) = field else { continue };
+            let [Value::Byte(code), Value::Variant(value)] = parts.as_slice() else {
+                continue;
+            };
+            match (*code, value.as_ref()) {
+                (1, Value::Path(path)) => message.path = Some(path.clone()),
+                (2, Value::Str(s)) => message.interface = Some(s.clone()),
+                (3, Value::Str(s)) => message.member = Some(s.clone()),
+                (4, Value::Str(s)) => message.error_name = Some(s.clone()),
+                (5, Value::U32(serial)) => message.reply_serial = Some(*serial),
+                (6, Value::Str(s)) => message.destination = Some(s.clone()),
+                (7, Value::Str(s)) => message.sender = Some(s.clone()),
+                (8, Value::Signature(s)) => signature = s.clone(),
+                _ => {}
+            }
+        }
+
+        // The body is aligned as if it started the buffer, which it does at
+        // an 8-byte boundary
+        let mut body = Decoder { bytes: &bytes[body_start..], pos: 0, big_endian };
+        let mut rest = signature.as_str();
+        while !rest.is_empty() {
+            let (ty, tail) = split_type(rest)?;
+            message.body.push(body.get(ty)?);
+            rest = tail;
+        }
+        Ok(message)
+    }
+}
+
+fn field(code: u8, value: Value) -> Value {
+    Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))])
+}
+
+/// A D-Bus value
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Byte(u8),
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    /// An index into the message's file descriptors (never sent by us)
+    UnixFd(u32),
+    Str(String),
+    Path(String),
+    Signature(String),
+    /// The element signature and the elements
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::I16(_) => "n".to_string(),
+            Value::U16(_) => "q".to_string(),
+            Value::I32(_) => "i".to_string(),
+            Value::U32(_) => "u".to_string(),
+            Value::I64(_) => "x".to_string(),
+            Value::U64(_) => "t".to_string(),
+            Value::Double(_) => "d".to_string(),
+            Value::UnixFd(_) => "h".to_string(),
+            Value::Str(_) => "s".to_string(),
+            Value::Path(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                format!("({})", fields.iter().map(Value::signature).collect::<String>())
+            }
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Value::Variant(_) => "v".to_string(),
+        }
+    }
+}
+
+/// Alignment of a type, given its signature
+fn alignment(ty: &str) -> usize {
+    match ty.as_bytes().first() {
+        Some(b'n' | b'q') => 2,
+        Some(b'b' | b'i' | b'u' | b'h' | b's' | b'o' | b'a') => 4,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 1,
+    }
+}
+
+/// Splits the first complete type off a signature
+fn split_type(signature: &str) -> io::Result<(&str, &str)> {
+    let bytes = signature.as_bytes();
+    let mut depth = 0usize;
+    for (i, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'a' => continue,
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' => {
+                depth = depth.checked_sub(1).ok_or_else(|| invalid_data("bad signature"))?;
+            }
+            _ => {}
+        }
+        if depth == 0 {
+            return Ok(signature.split_at(i + 1));
+        }
+    }
+    Err(invalid_data("bad signature"))
+}
+
+/// Marshals values in little-endian byte order
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn align(&mut self, alignment: usize) {
+        self.bytes.resize(align_up(self.bytes.len(), alignment), 0);
+    }
+
+    fn put_str(&mut self, s: &str) {
+        self.put(&Value::U32(s.len() as u32));
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+    }
+
+    fn put(&mut self, value: &Value) {
+        self.align(alignment(&value.signature()));
+        match value {
+            Value::Byte(b) => self.bytes.push(*b),
+            Value::Bool(b) => self.bytes.extend_from_slice(&u32::from(*b).to_le_bytes()),
+            Value::I16(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::U16(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::I32(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::U32(n) | Value::UnixFd(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::I64(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::U64(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::Double(n) => self.bytes.extend_from_slice(&n.to_le_bytes()),
+            Value::Str(s) | Value::Path(s) => self.put_str(s),
+            Value::Signature(s) => {
+                self.bytes.push(s.len() as u8);
+                self.bytes.extend_from_slice(s.as_bytes());
+                self.bytes.push(0);
+            }
+            Value::Array(element, items) => {
+                let length_at = self.bytes.len();
+                self.bytes.extend_from_slice(&[0; 4]);
+                // The padding before the first element doesn't count as data
+                self.align(alignment(element));
+                let start = self.bytes.len();
+                for item in items {
+                    self.put(item);
+                }
+                let length = (self.bytes.len() - start) as u32;
+                self.bytes[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                for field in fields {
+                    self.put(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.put(key);
+                self.put(value);
+            }
+            Value::Variant(inner) => {
+                self.put(&Value::Signature(inner.signature()));
+                self.put(inner);
+            }
+        }
+    }
+}
+
+/// Unmarshals values in either byte order
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> io::Result<&[u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| invalid_data("message truncated"))?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn align(&mut self, alignment: usize) -> io::Result<()> {
+        let padding = align_up(self.pos, alignment) - self.pos;
+        self.take(padding).map(|_| ())
+    }
+
+    fn fixed<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        self.align(N)?;
+        let mut bytes: [u8; N] = self.take(N)?.try_into().expect("took N bytes");
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.fixed().map(u32::from_le_bytes)
+    }
+
+    fn string(&mut self, len: usize) -> io::Result<String> {
+        let bytes = self.take(len + 1)?;
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| invalid_data("string is not UTF-8"))
+    }
+
+    /// Reads one value of the complete type `ty`
+    fn get(&mut self, ty: &str) -> io::Result<Value> {
+        Ok(match ty.as_bytes()[0] {
+            b'y' => Value::Byte(self.take(1)?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::I16(self.fixed().map(i16::from_le_bytes)?),
+            b'q' => Value::U16(self.fixed().map(u16::from_le_bytes)?),
+            b'i' => Value::I32(self.fixed().map(i32::from_le_bytes)?),
+            b'u' => Value::U32(self.u32()?),
+            b'h' => Value::UnixFd(self.u32()?),
+            b'x' => Value::I64(self.fixed().map(i64::from_le_bytes)?),
+            b't' => Value::U64(self.fixed().map(u64::from_le_bytes)?),
+            b'd' => Value::Double(self.fixed().map(f64::from_le_bytes)?),
+            b's' => {
+                let len = self.u32()? as usize;
+                Value::Str(self.string(len)?)
+            }
+            b'o' => {
+                let len = self.u32()? as usize;
+                Value::Path(self.string(len)?)
+            }
+            b'g' => {
+                let len = self.take(1)?[0] as usize;
+                Value::Signature(self.string(len)?)
+            }
+            b'v' => {
+                let len = self.take(1)?[0] as usize;
+                let signature = self.string(len)?;
+                let (inner, rest) = split_type(&signature)?;
+                if !rest.is_empty() {
+                    return Err(invalid_data("variant holds more than one type"));
+                }
+                Value::Variant(Box::new(self.get(inner)?))
+            }
+            b'a' => {
+                let element = &ty[1..];
+                let len = self.u32()? as usize;
+                self.align(alignment(element))?;
+                let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len());
+                let end = end.ok_or_else(|| invalid_data("message truncated"))?;
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.get(element)?);
+                }
+                Value::Array(element.to_string(), items)
+            }
+            b'(' => {
+                self.align(8)?;
+                let mut rest = &ty[1..ty.len() - 1];
+                let mut fields = Vec::new();
+                while !rest.is_empty() {
+                    let (field, tail) = split_type(rest)?;
+                    fields.push(self.get(field)?);
+                    rest = tail;
+                }
+                Value::Struct(fields)
+            }
+            b'{' => {
+                self.align(8)?;
+                let (key, value) = split_type(&ty[1..ty.len() - 1])?;
+                Value::DictEntry(Box::new(self.get(key)?), Box::new(self.get(value)?))
+            }
+            _ => return Err(invalid_data("unknown type in signature")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditEvent, ReasonCode};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+
+    /// A private `dbus-daemon --session` that goes away with the test
+    struct TestBus {
+        daemon: Child,
+        dir: PathBuf,
+        address: String,
+    }
+
+    impl TestBus {
+        fn start(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("bg-muter-dbus-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&dir).unwrap();
+            let mut daemon = Command::new("dbus-daemon")
+                .arg("--session")
+                .arg("--nofork")
+                .arg("--print-address")
+                .arg(format!("--address=unix:path={}", dir.join("bus").display()))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon should be installed");
+
+            // The address is printed once the bus is listening
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+            let address = address.trim().to_string();
+            Self { daemon, dir, address }
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Answers like a running instance with game.exe muted
+    fn handler(requests: Arc<Mutex<Vec<Request>>>) -> Handler {
+        Arc::new(move |request| {
+            requests.lock().push(request.clone());
+            match request {
+                Request::Status => Response::success(
+                    serde_json::json!({
+                        "muting_enabled": true,
+                        "muted_apps": [
+                            { "pid": 2, "process_name": "game.exe" },
+                            { "pid": 3, "process_name": "game.exe" },
+                        ],
+                    }),
+                    "",
+                ),
+                Request::Config => {
+                    Response::success(serde_json::json!({ "audibility_policy": "visible" }), "")
+                }
+                Request::Toggle => {
+                    Response::success(serde_json::json!({ "muting_enabled": false }), "")
+                }
+                Request::Explain { pid } => {
+                    Response::success(Json::Null, format!("PID {} is audible", pid))
+                }
+                Request::Snooze { seconds } if *seconds > 60 => Response::failure("too long"),
+                _ => Response::success(Json::Null, ""),
+            }
+        })
+    }
+
+    fn call(
+        client: &mut Connection,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> io::Result<Vec<Value>> {
+        let message = Message::method_call(BUS_NAME, OBJECT_PATH, interface, member, body);
+        client.call(message).map(|reply| reply.body)
+    }
+
+    fn variant(value: Value) -> Value {
+        Value::Variant(Box::new(value))
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut message = Message::signal(
+            OBJECT_PATH,
+            PROPERTIES,
+            "PropertiesChanged",
+            vec![
+                Value::Str(INTERFACE.to_string()),
+                Value::Array("{sv}".to_string(), vec![dict_entry(("On", Value::Bool(true)))]),
+                Value::Array("s".to_string(), Vec::new()),
+                Value::Struct(vec![Value::Byte(7), Value::U64(1 << 40), Value::I16(-2)]),
+            ],
+        );
+        message.serial = 9;
+        let bytes = message.encode();
+        assert_eq!(Message::decode(&bytes, false).unwrap(), message);
+
+        assert_eq!(split_type("a{sv}as").unwrap(), ("a{sv}", "as"));
+        assert!(split_type("a").is_err());
+        assert_eq!(unescape_address("/tmp/a%2cb").unwrap(), b"/tmp/a,b");
+        assert!(parent_node("/org").unwrap().contains("<node name=\"bgmuter\"/>"));
+        assert_eq!(parent_node("/net"), None);
+    }
+
+    #[test]
+    fn test_service_on_a_private_bus() {
+        let bus = TestBus::start("service");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        spawn_at(&bus.address, handler(requests.clone())).unwrap();
+        // The name is taken, so a second instance can't serve
+        assert!(spawn_at(&bus.address, handler(requests.clone())).is_err());
+
+        let mut client = Connection::connect(&bus.address).unwrap();
+        // Only a safety net, so a broken service fails the test instead of hanging it
+        let timeout = std::time::Duration::from_secs(10);
+        client.reader.get_ref().set_read_timeout(Some(timeout)).unwrap();
+
+        // Methods
+        let toggled = call(&mut client, INTERFACE, "Toggle", vec![]).unwrap();
+        assert_eq!(toggled, vec![Value::Bool(false)]);
+        let exclude = vec![Value::Str("Game.exe".to_string()), Value::Bool(false)];
+        assert!(call(&mut client, INTERFACE, "Exclude", exclude).unwrap().is_empty());
+        let explained = call(&mut client, INTERFACE, "Explain", vec![Value::U32(42)]).unwrap();
+        assert_eq!(explained, vec![Value::Str("PID 42 is audible".to_string())]);
+        let refused = call(&mut client, INTERFACE, "Snooze", vec![Value::U64(3600)]).unwrap_err();
+        assert_eq!(refused.to_string(), format!("{}: too long", ERROR_FAILED));
+        assert!(call(&mut client, INTERFACE, "Snooze", vec![Value::U32(1)]).is_err());
+        let exclude = Request::Exclude { app: "Game.exe".to_string(), remove: false };
+        assert!(requests.lock().contains(&exclude));
+
+        // Properties
+        let get = |client: &mut Connection, name: &str| {
+            let args = vec![Value::Str(INTERFACE.to_string()), Value::Str(name.to_string())];
+            call(client, PROPERTIES, "Get", args).unwrap()
+        };
+        assert_eq!(get(&mut client, "Enabled"), vec![variant(Value::Bool(true))]);
+        let profile = Value::Str("visible".to_string());
+        assert_eq!(get(&mut client, "ActiveProfile"), vec![variant(profile)]);
+        let muted = Value::Array("s".to_string(), vec![Value::Str("game.exe".to_string())]);
+        assert_eq!(get(&mut client, "MutedApps"), vec![variant(muted)]);
+        let all = call(&mut client, PROPERTIES, "GetAll", vec![Value::Str(INTERFACE.to_string())]);
+        let all = all.unwrap();
+        let [Value::Array(_, entries)] = all.as_slice() else {
+            panic!("GetAll should return a{{sv}}");
+        };
+        assert_eq!(entries.len(), 3);
+        let xml = call(&mut client, INTROSPECTABLE, "Introspect", vec![]).unwrap();
+        assert!(matches!(&xml[0], Value::Str(xml) if xml.contains("name=\"AppMuted\"")));
+
+        // Signals
+        let rule = format!("type='signal',interface='{}'", INTERFACE);
+        let add_match = Message::method_call(
+            BUS_DAEMON,
+            BUS_DAEMON_PATH,
+            BUS_DAEMON,
+            "AddMatch",
+            vec![Value::Str(rule)],
+        );
+        client.call(add_match).unwrap();
+        let (action, reason) = (AuditAction::Muted, ReasonCode::Background);
+        events::publish(AuditEvent::new(4242, "dbus-test.exe", action, reason, None).into());
+        let signal = loop {
+            let message = client.read_message().unwrap();
+            let app = message.body.first().cloned();
+            if message.member.as_deref() == Some("AppMuted")
+                && app == Some(Value::Str("dbus-test.exe".to_string()))
+            {
+                break message;
+            }
+        };
+        assert_eq!(
+            signal.body,
+            vec![
+                Value::Str("dbus-test.exe".to_string()),
+                Value::U32(4242),
+                Value::Str(ReasonCode::Background.as_str().to_string()),
+            ]
+        );
+        assert_eq!(signal.path.as_deref(), Some(OBJECT_PATH));
+    }
+}
@@ -0,0 +1,135 @@
+//! Event bus module
+//! Passes engine events on to whoever subscribed, such as IPC and WebSocket
+//! clients.
+//! Subscribers that fall behind miss events instead of slowing the engine.
+
+use crate::audit::{AuditAction, AuditEvent, FocusChange};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Events buffered per subscriber before newer ones are dropped
+const SUBSCRIBER_BACKLOG: usize = 256;
+
+static SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+
+/// Something subscribers can be told about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    AppMuted(AuditEvent),
+    AppUnmuted(AuditEvent),
+    /// The app that counts as foreground changed
+    ForegroundChanged(FocusChange),
+    /// Muting was turned on or off
+    MutingToggled { enabled: bool },
+}
+
+impl Event {
+    /// The name used in the JSON `event` field and for hooks
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::AppMuted(_) => "app-muted",
+            Event::AppUnmuted(_) => "app-unmuted",
+            Event::ForegroundChanged(_) => "foreground-changed",
+            Event::MutingToggled { .. } => "muting-toggled",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::AppMuted(event) | Event::AppUnmuted(event) => write!(f, "{}", event),
+            Event::ForegroundChanged(change) => match (&change.to_app, change.to_pid) {
+                (Some(app), Some(pid)) => write!(f, "foreground: {} ({})", app, pid),
+                (None, Some(pid)) => write!(f, "foreground: PID {}", pid),
+                _ => write!(f, "foreground: none"),
+            },
+            Event::MutingToggled { enabled } => {
+                write!(f, "muting: {}", if *enabled { "on" } else { "off" })
+            }
+        }
+    }
+}
+
+impl From<AuditEvent> for Event {
+    fn from(event: AuditEvent) -> Self {
+        match event.action {
+            AuditAction::Muted => Event::AppMuted(event),
+            AuditAction::Unmuted => Event::AppUnmuted(event),
+        }
+    }
+}
+
+/// Starts receiving every event published from now on
+pub fn subscribe() -> Receiver<Event> {
+    let (tx, rx) = crossbeam_channel::bounded(SUBSCRIBER_BACKLOG);
+    SUBSCRIBERS.lock().push(tx);
+    rx
+}
+
+/// Sends an event to all subscribers without blocking
+pub fn publish(event: Event) {
+    SUBSCRIBERS.lock().retain(|tx| match tx.try_send(event.clone()) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            log::debug!("Event subscriber is behind, dropping an event");
+            true
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
+
+/// Stores the shared muting flag, publishing `MutingToggled` if it changed
+///
+/// Every write of the flag goes through here, so subscribers and hooks hear
+/// about each change exactly once.
+pub fn store_muting_flag(flag: &AtomicBool, enabled: bool) {
+    if flag.swap(enabled, Ordering::SeqCst) != enabled {
+        publish(Event::MutingToggled { enabled });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::ReasonCode;
+
+    #[test]
+    fn test_publish_reaches_subscribers() {
+        let rx = subscribe();
+        let event =
+            AuditEvent::new(7, "game.exe", AuditAction::Muted, ReasonCode::Background, None);
+        publish(event.clone().into());
+
+        // Other tests may publish too, so look for ours
+        assert!(rx.try_iter().any(|e| e == Event::AppMuted(event.clone())));
+
+        let json = serde_json::to_value(Event::from(event)).unwrap();
+        assert_eq!(json["event"], "app-muted");
+        assert_eq!(json["app"], "game.exe");
+
+        let json = serde_json::to_value(Event::MutingToggled { enabled: false }).unwrap();
+        assert_eq!(json["event"], Event::MutingToggled { enabled: false }.name());
+        assert_eq!(json["enabled"], false);
+    }
+
+    #[test]
+    fn test_muting_flag_publishes_changes() {
+        let rx = subscribe();
+        let flag = AtomicBool::new(true);
+        store_muting_flag(&flag, true);
+        store_muting_flag(&flag, false);
+        assert!(!flag.load(Ordering::SeqCst));
+
+        // Other tests may toggle too, so only count events since subscribing
+        let toggles: Vec<_> = rx
+            .try_iter()
+            .filter(|e| matches!(e, Event::MutingToggled { .. }))
+            .collect();
+        assert!(toggles.contains(&Event::MutingToggled { enabled: false }));
+    }
+}
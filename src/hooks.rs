@@ -0,0 +1,336 @@
+//! Hooks module
+//! Runs the user's commands from `Config::hooks` when events are published.
+//! Each hook gets the event as `BG_MUTER_*` environment variables and as JSON
+//! on stdin, and runs on its own thread with a timeout, so a slow script never
+//! holds up the engine.
+
+use crate::config::{Config, HookCommands};
+use crate::events::{self, Event};
+use parking_lot::RwLock;
+use std::io::{self, Write};
+use std::os::windows::io::AsRawHandle;
+use std::os::windows::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
+use windows::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, TerminateJobObject,
+};
+use windows::Win32::System::Threading::{
+    OpenThread, ResumeThread, CREATE_NO_WINDOW, CREATE_SUSPENDED, THREAD_SUSPEND_RESUME,
+};
+
+/// Hooks allowed to run at once; events beyond that skip their hook
+const MAX_RUNNING: usize = 8;
+
+/// How often a running hook is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts the thread that runs hooks for published events
+pub fn spawn(config: Arc<RwLock<Config>>) -> io::Result<()> {
+    let events = events::subscribe();
+    thread::Builder::new()
+        .name("hooks".to_string())
+        .spawn(move || {
+            for event in events {
+                let (command, timeout) = {
+                    let config = config.read();
+                    let command = command_for(&config.hooks, &event).map(str::to_string);
+                    (command, Duration::from_secs(config.hook_timeout_secs))
+                };
+                if let Some(command) = command {
+                    start(command, event, timeout);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Gets the command configured for an event, if any
+fn command_for<'a>(hooks: &'a HookCommands, event: &Event) -> Option<&'a str> {
+    let command = match event {
+        Event::AppMuted(_) => &hooks.app_muted,
+        Event::AppUnmuted(_) => &hooks.app_unmuted,
+        Event::ForegroundChanged(_) => &hooks.foreground_changed,
+        Event::MutingToggled { .. } => &hooks.muting_toggled,
+    };
+    command.as_deref().map(str::trim).filter(|c| !c.is_empty())
+}
+
+/// Runs a hook on its own thread unless too many are already running
+fn start(command: String, event: Event, timeout: Duration) {
+    if RUNNING.fetch_add(1, Ordering::SeqCst) >= MAX_RUNNING {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+        log::warn!("Skipping the {} hook: {} hooks still running", event.name(), MAX_RUNNING);
+        return;
+    }
+
+    let spawned = thread::Builder::new().name("hook".to_string()).spawn(move || {
+        if let Err(e) = run(&command, &event, timeout) {
+            log::warn!("The {} hook `{}` failed: {}", event.name(), command, e);
+        }
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    });
+    if let Err(e) = spawned {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+        log::warn!("Failed to start a hook thread: {}", e);
+    }
+}
+
+/// Runs a hook and waits for it, killing it and everything it started once
+/// `timeout` has passed
+fn run(command: &str, event: &Event, timeout: Duration) -> io::Result<()> {
+    let mut cmd = shell_command(command);
+    cmd.envs(environment(event))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let (mut child, job) = spawn_in_job(cmd)?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its input may close stdin early; that's fine
+        let _ = writeln!(stdin, "{}", serde_json::to_string(event)?);
+    }
+
+    match wait(&mut child, job.as_ref(), timeout)? {
+        Some(status) if !status.success() => {
+            log::warn!("The {} hook `{}` exited with {}", event.name(), command, status);
+        }
+        Some(_) => {}
+        None => {
+            log::warn!("The {} hook `{}` timed out after {:?}", event.name(), command, timeout);
+        }
+    }
+    Ok(())
+}
+
+/// Starts the shell suspended and resumes it only once it is in a new job,
+/// so not even its first child can escape the job
+///
+/// Without a job the shell runs on its own and a timeout only ends the shell.
+fn spawn_in_job(mut cmd: Command) -> io::Result<(Child, Option<HookJob>)> {
+    let job = match HookJob::new() {
+        Ok(job) => job,
+        Err(e) => {
+            log::debug!("Hook not in a job, a timeout only ends the shell: {}", e);
+            return Ok((cmd.spawn()?, None));
+        }
+    };
+
+    let mut child = cmd.creation_flags(CREATE_NO_WINDOW.0 | CREATE_SUSPENDED.0).spawn()?;
+    let assigned = job.assign(&child);
+    if let Err(e) = resume(child.id()) {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+    match assigned {
+        Ok(()) => Ok((child, Some(job))),
+        Err(e) => {
+            log::debug!("Hook not in a job, a timeout only ends the shell: {}", e);
+            Ok((child, None))
+        }
+    }
+}
+
+/// Resumes the threads of a process started with `CREATE_SUSPENDED`
+fn resume(pid: u32) -> io::Result<()> {
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0)?;
+        let mut entry = THREADENTRY32 {
+            dwSize: std::mem::size_of::<THREADENTRY32>() as u32,
+            ..Default::default()
+        };
+        let mut resumed = false;
+        let mut next = Thread32First(snapshot, &mut entry);
+        while next.is_ok() {
+            if entry.th32OwnerProcessID == pid {
+                if let Ok(thread) = OpenThread(THREAD_SUSPEND_RESUME, false, entry.th32ThreadID) {
+                    resumed |= ResumeThread(thread) != u32::MAX;
+                    let _ = CloseHandle(thread);
+                }
+            }
+            next = Thread32Next(snapshot, &mut entry);
+        }
+        let _ = CloseHandle(snapshot);
+
+        if resumed {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("could not resume process {}", pid)))
+        }
+    }
+}
+
+/// Waits for the hook to exit; once `timeout` has passed, ends its job (or
+/// just the shell without one) and returns None
+fn wait(
+    child: &mut Child,
+    job: Option<&HookJob>,
+    timeout: Duration,
+) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            match job {
+                Some(job) => job.terminate(),
+                None => {
+                    let _ = child.kill();
+                }
+            }
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The event's details as environment variables
+fn environment(event: &Event) -> Vec<(&'static str, String)> {
+    let mut vars = vec![("BG_MUTER_EVENT", event.name().to_string())];
+    match event {
+        Event::AppMuted(audit) | Event::AppUnmuted(audit) => {
+            vars.push(("BG_MUTER_APP", audit.app.clone()));
+            vars.push(("BG_MUTER_PID", audit.pid.to_string()));
+            vars.push(("BG_MUTER_REASON", audit.reason.as_str().to_string()));
+        }
+        Event::ForegroundChanged(change) => {
+            if let Some(app) = &change.to_app {
+                vars.push(("BG_MUTER_APP", app.clone()));
+            }
+            if let Some(pid) = change.to_pid {
+                vars.push(("BG_MUTER_PID", pid.to_string()));
+            }
+        }
+        Event::MutingToggled { enabled } => {
+            vars.push(("BG_MUTER_ENABLED", enabled.to_string()));
+        }
+    }
+    vars
+}
+
+/// Runs the command line through the shell, so pipes and quoting work as typed
+///
+/// `CREATE_NO_WINDOW` keeps console hooks from flashing a window.
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").raw_arg(command).creation_flags(CREATE_NO_WINDOW.0);
+    cmd
+}
+
+/// A job object holding a hook's shell and every process it starts, so a
+/// timeout ends the whole tree rather than just cmd.exe
+///
+/// The job isn't killed on close: programs a hook leaves running on purpose
+/// (`start notepad`) outlive a hook that finished in time.
+struct HookJob(HANDLE);
+
+impl HookJob {
+    fn new() -> io::Result<Self> {
+        unsafe { Ok(Self(CreateJobObjectW(None, PCWSTR::null())?)) }
+    }
+
+    fn assign(&self, child: &Child) -> io::Result<()> {
+        unsafe { Ok(AssignProcessToJobObject(self.0, HANDLE(child.as_raw_handle()))?) }
+    }
+
+    /// Number of processes still in the job
+    #[cfg(test)]
+    fn active_processes(&self) -> io::Result<u32> {
+        use windows::Win32::System::JobObjects::{
+            JobObjectBasicAccountingInformation, QueryInformationJobObject,
+            JOBOBJECT_BASIC_ACCOUNTING_INFORMATION,
+        };
+
+        let mut info = JOBOBJECT_BASIC_ACCOUNTING_INFORMATION::default();
+        unsafe {
+            QueryInformationJobObject(
+                self.0,
+                JobObjectBasicAccountingInformation,
+                &mut info as *mut _ as *mut _,
+                std::mem::size_of_val(&info) as u32,
+                None,
+            )?;
+        }
+        Ok(info.ActiveProcesses)
+    }
+
+    fn terminate(&self) {
+        unsafe {
+            let _ = TerminateJobObject(self.0, 1);
+        }
+    }
+}
+
+impl Drop for HookJob {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditEvent, ReasonCode};
+    use std::fs;
+
+    #[test]
+    fn test_hook_gets_event_details() {
+        let hooks = HookCommands {
+            app_muted: Some("  ".to_string()),
+            muting_toggled: Some("rem".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(command_for(&hooks, &Event::MutingToggled { enabled: true }), Some("rem"));
+        let event = Event::from(AuditEvent::new(
+            9,
+            "game.exe",
+            AuditAction::Muted,
+            ReasonCode::Background,
+            None,
+        ));
+        assert_eq!(command_for(&hooks, &event), None);
+
+        let out = std::env::temp_dir().join(format!("bg-muter-hook-{}", std::process::id()));
+        let command = format!(
+            "echo %BG_MUTER_EVENT% %BG_MUTER_APP% %BG_MUTER_REASON%> \"{0}\" \
+             & findstr \"^\" >> \"{0}\"",
+            out.display()
+        );
+        run(&command, &event, Duration::from_secs(5)).unwrap();
+
+        let written = fs::read_to_string(&out).unwrap();
+        let _ = fs::remove_file(&out);
+        let mut lines = written.lines();
+        assert_eq!(lines.next(), Some("app-muted game.exe background"));
+        let parsed: Event = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(parsed, event);
+    }
+
+    #[test]
+    fn test_timed_out_hook_ends_with_its_children() {
+        // The inner shell waits for a signal nobody sends, so only the
+        // timeout ends the hook
+        let command = format!("cmd /C waitfor bg-muter-hook-{}", std::process::id());
+        let (mut child, job) = spawn_in_job(shell_command(&command)).unwrap();
+        let job = job.expect("hook should run in a job");
+        assert!(job.active_processes().unwrap() >= 1);
+
+        assert!(wait(&mut child, Some(&job), Duration::ZERO).unwrap().is_none());
+        assert_eq!(job.active_processes().unwrap(), 0);
+    }
+}
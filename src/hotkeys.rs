@@ -0,0 +1,243 @@
+//! Global hotkey module
+//! Parses hotkey bindings from the config and registers them with
+//! RegisterHotKey so they work while other apps (e.g. games) have focus.
+
+use crate::config::HotkeyBindings;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    GetAsyncKeyState, RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL,
+    MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, VIRTUAL_KEY, VK_BACK, VK_DELETE, VK_DOWN, VK_END, VK_ESCAPE,
+    VK_F1, VK_HOME, VK_INSERT, VK_LEFT, VK_MEDIA_NEXT_TRACK, VK_MEDIA_PLAY_PAUSE,
+    VK_MEDIA_PREV_TRACK, VK_NEXT, VK_PAUSE, VK_PRIOR, VK_RETURN, VK_RIGHT, VK_SCROLL, VK_SPACE,
+    VK_TAB, VK_UP, VK_VOLUME_MUTE,
+};
+use windows::Win32::UI::WindowsAndMessaging::{MSG, WM_HOTKEY};
+
+/// Actions that can be bound to a global hotkey
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    /// Toggle muting on/off
+    ToggleMuting,
+    /// Add or remove the foreground app from the exclusion list
+    ToggleExcludeCurrent,
+    /// Keep the foreground app audible until pinned again
+    PinCurrent,
+    /// Unmute all background apps while the key is held
+    Peek,
+}
+
+impl HotkeyAction {
+    pub const ALL: [HotkeyAction; 4] = [
+        HotkeyAction::ToggleMuting,
+        HotkeyAction::ToggleExcludeCurrent,
+        HotkeyAction::PinCurrent,
+        HotkeyAction::Peek,
+    ];
+
+    /// Gets the RegisterHotKey id for this action
+    fn id(self) -> i32 {
+        self as i32 + 1
+    }
+
+    /// Maps a RegisterHotKey id back to its action
+    fn from_id(id: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.id() == id)
+    }
+
+    /// Gets the configured binding for this action
+    fn binding(self, bindings: &HotkeyBindings) -> Option<&str> {
+        match self {
+            HotkeyAction::ToggleMuting => bindings.toggle_muting.as_deref(),
+            HotkeyAction::ToggleExcludeCurrent => bindings.toggle_exclude_current.as_deref(),
+            HotkeyAction::PinCurrent => bindings.pin_current.as_deref(),
+            HotkeyAction::Peek => bindings.peek.as_deref(),
+        }
+    }
+}
+
+/// A parsed key combination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: HOT_KEY_MODIFIERS,
+    pub vk: VIRTUAL_KEY,
+}
+
+/// Parses a binding such as "Ctrl+Alt+M", "Shift+F9" or "Win+Space"
+pub fn parse_hotkey(text: &str) -> Result<Hotkey, String> {
+    let mut modifiers = HOT_KEY_MODIFIERS(0);
+    let mut vk = None;
+
+    for part in text.split('+').map(str::trim) {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "control" => modifiers |= MOD_CONTROL,
+            "alt" => modifiers |= MOD_ALT,
+            "shift" => modifiers |= MOD_SHIFT,
+            "win" | "super" => modifiers |= MOD_WIN,
+            key => {
+                if vk.is_some() {
+                    return Err(format!("'{}' has more than one key", text));
+                }
+                vk = Some(parse_key(key).ok_or_else(|| format!("unknown key '{}'", part))?);
+            }
+        }
+    }
+
+    let vk = vk.ok_or_else(|| format!("'{}' has no key", text))?;
+    Ok(Hotkey { modifiers, vk })
+}
+
+/// Maps a lowercase key name to its virtual-key code
+fn parse_key(key: &str) -> Option<VIRTUAL_KEY> {
+    let bytes = key.as_bytes();
+    if bytes.len() == 1 && bytes[0].is_ascii_alphanumeric() {
+        // Letters and digits use their uppercase ASCII code
+        return Some(VIRTUAL_KEY(bytes[0].to_ascii_uppercase() as u16));
+    }
+
+    if let Some(n) = key.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        if !(1..=24).contains(&n) {
+            return None;
+        }
+        return Some(VIRTUAL_KEY(VK_F1.0 + n - 1));
+    }
+
+    let vk = match key {
+        "space" => VK_SPACE,
+        "enter" | "return" => VK_RETURN,
+        "tab" => VK_TAB,
+        "esc" | "escape" => VK_ESCAPE,
+        "backspace" => VK_BACK,
+        "pause" => VK_PAUSE,
+        "scrolllock" => VK_SCROLL,
+        "insert" => VK_INSERT,
+        "delete" => VK_DELETE,
+        "home" => VK_HOME,
+        "end" => VK_END,
+        "pageup" => VK_PRIOR,
+        "pagedown" => VK_NEXT,
+        "up" => VK_UP,
+        "down" => VK_DOWN,
+        "left" => VK_LEFT,
+        "right" => VK_RIGHT,
+        "volumemute" => VK_VOLUME_MUTE,
+        "mediaplaypause" => VK_MEDIA_PLAY_PAUSE,
+        "medianext" => VK_MEDIA_NEXT_TRACK,
+        "mediaprev" => VK_MEDIA_PREV_TRACK,
+        _ => return None,
+    };
+    Some(vk)
+}
+
+/// Owns the hotkeys registered for the calling thread
+///
+/// WM_HOTKEY is posted to the thread queue of the thread that created
+/// the manager, so it must live on the tray thread.
+pub struct HotkeyManager {
+    registered: Vec<(HotkeyAction, Hotkey)>,
+}
+
+impl HotkeyManager {
+    /// Registers all configured bindings (failures are logged and skipped)
+    pub fn register(bindings: &HotkeyBindings) -> Self {
+        let mut registered = Vec::new();
+
+        for action in HotkeyAction::ALL {
+            let Some(text) = action.binding(bindings) else {
+                continue;
+            };
+
+            let hotkey = match parse_hotkey(text) {
+                Ok(h) => h,
+                Err(e) => {
+                    log::warn!("Invalid hotkey for {:?}: {}", action, e);
+                    continue;
+                }
+            };
+
+            // Suppress auto-repeat so holding a key (e.g. peek) fires only once
+            let modifiers = hotkey.modifiers | MOD_NOREPEAT;
+            match unsafe { RegisterHotKey(None, action.id(), modifiers, hotkey.vk.0 as u32) } {
+                Ok(()) => {
+                    log::info!("Registered hotkey {} for {:?}", text, action);
+                    registered.push((action, hotkey));
+                }
+                Err(e) => log::warn!("Failed to register hotkey {} ({:?}): {}", text, action, e),
+            }
+        }
+
+        Self { registered }
+    }
+
+    /// Replaces the registered hotkeys with a new set of bindings
+    pub fn reregister(&mut self, bindings: &HotkeyBindings) {
+        self.unregister_all();
+        *self = Self::register(bindings);
+    }
+
+    fn unregister_all(&mut self) {
+        for (action, _) in self.registered.drain(..) {
+            unsafe {
+                let _ = UnregisterHotKey(None, action.id());
+            }
+        }
+    }
+
+    /// Maps a thread message to a hotkey action
+    pub fn action_for_message(msg: &MSG) -> Option<HotkeyAction> {
+        if msg.hwnd.0.is_null() && msg.message == WM_HOTKEY {
+            HotkeyAction::from_id(msg.wParam.0 as i32)
+        } else {
+            None
+        }
+    }
+
+    /// Checks if the key bound to an action is still held down
+    pub fn is_held(&self, action: HotkeyAction) -> bool {
+        self.registered
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, hotkey)| unsafe { GetAsyncKeyState(hotkey.vk.0 as i32) } < 0)
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for HotkeyManager {
+    fn drop(&mut self) {
+        self.unregister_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hotkey() {
+        let hotkey = parse_hotkey("Ctrl+Alt+M").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_CONTROL | MOD_ALT);
+        assert_eq!(hotkey.vk, VIRTUAL_KEY(b'M' as u16));
+
+        let hotkey = parse_hotkey("shift + f9").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_SHIFT);
+        assert_eq!(hotkey.vk, VIRTUAL_KEY(VK_F1.0 + 8));
+
+        let hotkey = parse_hotkey("Win+Space").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_WIN);
+        assert_eq!(hotkey.vk, VK_SPACE);
+    }
+
+    #[test]
+    fn test_parse_hotkey_errors() {
+        assert!(parse_hotkey("Ctrl+Alt").is_err());
+        assert!(parse_hotkey("Ctrl+A+B").is_err());
+        assert!(parse_hotkey("Ctrl+Banana").is_err());
+        assert!(parse_hotkey("F25").is_err());
+    }
+
+    #[test]
+    fn test_action_ids_round_trip() {
+        for action in HotkeyAction::ALL {
+            assert_eq!(HotkeyAction::from_id(action.id()), Some(action));
+        }
+        assert_eq!(HotkeyAction::from_id(0), None);
+    }
+}
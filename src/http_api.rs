@@ -0,0 +1,703 @@
+//! Local HTTP API module
+//! An opt-in HTTP and WebSocket server on 127.0.0.1 for dashboards, OBS
+//! browser sources and stream deck plugins. Every request must carry the
+//! configured token, as `Authorization: Bearer <token>` or `?token=<token>`.
+
+use crate::control::{ControlContext, Request, Response};
+use crate::events;
+use crate::ipc::Handler;
+use crossbeam_channel::RecvTimeoutError;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use windows::Win32::Security::Cryptography::{BCryptGenRandom, BCRYPT_USE_SYSTEM_PREFERRED_RNG};
+
+/// Every endpoint lives under this path
+const API_PREFIX: &str = "/api/v1";
+
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Slow or stalled HTTP clients are dropped after this long
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fixed GUID from RFC 6455 that goes into the handshake
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest WebSocket frame accepted from a client
+const MAX_FRAME_BYTES: u64 = 64 * 1024;
+
+/// Quiet WebSockets are pinged this often, which also notices dead clients
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Starts the API on `127.0.0.1:<port>`
+pub fn spawn(context: ControlContext, port: u16, token: String) -> io::Result<SocketAddr> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    spawn_at(addr, token, Arc::new(move |request| context.execute(request)))
+}
+
+/// Starts the API on `addr`, returning the address actually bound
+pub fn spawn_at(addr: SocketAddr, token: String, handler: Handler) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    log::info!("HTTP API listening on http://{}{}", addr, API_PREFIX);
+
+    let token: Arc<str> = token.into();
+    thread::Builder::new()
+        .name("http-api".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("HTTP API accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let (token, handler) = (token.clone(), handler.clone());
+                let spawned = thread::Builder::new()
+                    .name("http-connection".to_string())
+                    .spawn(move || {
+                        if let Err(e) = serve_connection(stream, &token, &handler) {
+                            log::debug!("HTTP connection ended: {}", e);
+                        }
+                    });
+                if let Err(e) = spawned {
+                    log::warn!("Failed to start an HTTP connection thread: {}", e);
+                }
+            }
+        })?;
+    Ok(addr)
+}
+
+/// Makes a random API token
+pub fn generate_token() -> io::Result<String> {
+    let mut bytes = [0u8; 32];
+    unsafe {
+        BCryptGenRandom(None, &mut bytes, BCRYPT_USE_SYSTEM_PREFERRED_RNG).ok()?;
+    }
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A parsed HTTP request
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// What a request asks for
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// A control request, with the part of its answer to return
+    Call(Request, View),
+    /// The WebSocket event stream
+    Events,
+    NotFound,
+}
+
+#[derive(Debug, PartialEq)]
+enum View {
+    Full,
+    /// Only the sessions we muted
+    MutedOnly,
+    /// Only the excluded_apps list from the settings
+    ExcludedApps,
+}
+
+fn route(method: &str, path: &str) -> Route {
+    let Some(rest) = path.strip_prefix(API_PREFIX) else {
+        return Route::NotFound;
+    };
+    let segments: Vec<String> = rest
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let call = |request| Route::Call(request, View::Full);
+    match (method, segments.as_slice()) {
+        ("GET", ["status"]) => call(Request::Status),
+        ("GET", ["sessions"]) => call(Request::ListSessions),
+        ("GET", ["muted"]) => Route::Call(Request::ListSessions, View::MutedOnly),
+        ("POST", ["toggle"]) => call(Request::Toggle),
+        ("POST", ["enable"]) => call(Request::Enable),
+        ("POST", ["disable"]) => call(Request::Disable),
+        ("POST", ["snooze", seconds]) => match seconds.parse() {
+            Ok(seconds) => call(Request::Snooze { seconds }),
+            Err(_) => Route::NotFound,
+        },
+        ("GET", ["exclusions"]) => Route::Call(Request::Config, View::ExcludedApps),
+        ("PUT", ["exclusions", app]) => call(Request::Exclude {
+            app: app.to_string(),
+            remove: false,
+        }),
+        ("DELETE", ["exclusions", app]) => call(Request::Exclude {
+            app: app.to_string(),
+            remove: true,
+        }),
+        ("PUT", ["always-muted", app]) => call(Request::AlwaysMute {
+            app: app.to_string(),
+            remove: false,
+        }),
+        ("DELETE", ["always-muted", app]) => call(Request::AlwaysMute {
+            app: app.to_string(),
+            remove: true,
+        }),
+        ("GET", ["explain", pid]) => match pid.parse() {
+            Ok(pid) => call(Request::Explain { pid }),
+            Err(_) => Route::NotFound,
+        },
+        ("GET", ["events"]) => Route::Events,
+        _ => Route::NotFound,
+    }
+}
+
+impl View {
+    fn apply(&self, mut response: Response) -> Response {
+        if !response.ok {
+            return response;
+        }
+        match self {
+            View::Full => {}
+            View::MutedOnly => {
+                if let Value::Array(sessions) = &mut response.data {
+                    sessions.retain(|session| session["muted_by_us"] == Value::Bool(true));
+                    response.text = format!("{} muted", sessions.len());
+                }
+            }
+            View::ExcludedApps => {
+                let mut apps: Vec<String> =
+                    serde_json::from_value(response.data["excluded_apps"].take())
+                        .unwrap_or_default();
+                apps.sort();
+                response.text = apps.join(", ");
+                response.data = apps.into();
+            }
+        }
+        response
+    }
+}
+
+fn serve_connection(stream: TcpStream, token: &str, handler: &Handler) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            return write_json(&mut stream, 400, &Response::failure(e.to_string()));
+        }
+        Err(e) => return Err(e),
+    };
+
+    // CORS preflight for browser sources; it never carries the token
+    if request.method == "OPTIONS" {
+        return write_head(&mut stream, 204, &[], 0);
+    }
+    // A page on another site can't resolve its own name to us (DNS rebinding)
+    if !host_allowed(&request) {
+        return write_json(&mut stream, 403, &Response::failure("unexpected Host header"));
+    }
+    if !authorized(&request, token) {
+        return write_json(&mut stream, 401, &Response::failure("missing or wrong token"));
+    }
+
+    match route(&request.method, &request.path) {
+        Route::Call(call, view) => {
+            let response = view.apply(handler(&call));
+            let status = if response.ok { 200 } else { 400 };
+            write_json(&mut stream, status, &response)
+        }
+        Route::Events => serve_websocket(stream, reader, &request),
+        Route::NotFound => write_json(&mut stream, 404, &Response::failure("no such endpoint")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<HttpRequest> {
+    let mut lines = Vec::new();
+    let mut total = 0;
+    loop {
+        let mut line = String::new();
+        let limit = (MAX_HEADER_BYTES - total) as u64;
+        let read = (&mut *reader).take(limit).read_line(&mut line)?;
+        if read == 0 && lines.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no request"));
+        }
+        if !line.ends_with('\n') {
+            return Err(invalid("request head too large or cut off"));
+        }
+        total += read;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+
+    let mut request_line = lines[0].split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect();
+    let headers: Vec<(String, String)> = lines[1..]
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let request = HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        headers,
+    };
+
+    // Bodies aren't used by any endpoint, but must be consumed
+    let length: usize = match request.header("content-length") {
+        Some(length) => length.parse().map_err(|_| invalid("bad Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(invalid("request body too large"));
+    }
+    io::copy(&mut (&mut *reader).take(length as u64), &mut io::sink())?;
+    Ok(request)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn host_allowed(request: &HttpRequest) -> bool {
+    let Some(host) = request.header("host") else {
+        return true;
+    };
+    let name = host.split(':').next().unwrap_or_default();
+    name == "127.0.0.1" || name.eq_ignore_ascii_case("localhost")
+}
+
+fn authorized(request: &HttpRequest, token: &str) -> bool {
+    let given = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| request.query("token"));
+    match given {
+        Some(given) => !token.is_empty() && constant_time_eq(given.trim(), token),
+        None => false,
+    }
+}
+
+/// Compares without bailing out at the first difference
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn write_head(
+    stream: &mut impl Write,
+    status: u16,
+    extra_headers: &[(&str, &str)],
+    length: usize,
+) -> io::Result<()> {
+    let reason = match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        _ => "Not Found",
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, POST, PUT, DELETE, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Authorization\r\n",
+        status, reason
+    );
+    for (name, value) in extra_headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if status != 101 {
+        head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n", length));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+fn write_json(stream: &mut impl Write, status: u16, response: &Response) -> io::Result<()> {
+    let body = serde_json::to_string(response)?;
+    write_head(stream, status, &[("Content-Type", "application/json")], body.len())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+/// Upgrades to a WebSocket and pushes events until either side closes it
+fn serve_websocket(
+    mut stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    request: &HttpRequest,
+) -> io::Result<()> {
+    let is_upgrade = request
+        .header("upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let Some(key) = request.header("sec-websocket-key").filter(|_| is_upgrade) else {
+        let response = Response::failure("expected a WebSocket upgrade");
+        return write_json(&mut stream, 400, &response);
+    };
+
+    let accept = websocket_accept(key);
+    let headers = [
+        ("Upgrade", "websocket"),
+        ("Connection", "Upgrade"),
+        ("Sec-WebSocket-Accept", accept.as_str()),
+    ];
+    // Subscribe before answering so no event slips in between
+    let events = events::subscribe();
+    write_head(&mut stream, 101, &headers, 0)?;
+    stream.set_read_timeout(None)?;
+
+    let writer = Arc::new(Mutex::new(stream));
+    let reader_writer = writer.clone();
+    thread::Builder::new()
+        .name("websocket-reader".to_string())
+        .spawn(move || {
+            if let Err(e) = read_frames(reader, &reader_writer) {
+                log::debug!("WebSocket read ended: {}", e);
+            }
+            let _ = reader_writer.lock().shutdown(Shutdown::Both);
+        })?;
+
+    let result = loop {
+        let sent = match events.recv_timeout(PING_INTERVAL) {
+            Ok(event) => {
+                let text = serde_json::to_string(&event)?;
+                send_frame(&mut *writer.lock(), OPCODE_TEXT, text.as_bytes())
+            }
+            Err(RecvTimeoutError::Timeout) => send_frame(&mut *writer.lock(), OPCODE_PING, &[]),
+            Err(RecvTimeoutError::Disconnected) => break Ok(()),
+        };
+        if let Err(e) = sent {
+            break Err(e);
+        }
+    };
+    let _ = writer.lock().shutdown(Shutdown::Both);
+    result
+}
+
+/// Answers pings and close frames; the API is push-only, so data is ignored
+fn read_frames(mut reader: impl Read, writer: &Mutex<TcpStream>) -> io::Result<()> {
+    loop {
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header)?;
+        let opcode = header[0] & 0x0F;
+        let masked = header[1] & 0x80 != 0;
+
+        let length = match header[1] & 0x7F {
+            126 => {
+                let mut bytes = [0u8; 2];
+                reader.read_exact(&mut bytes)?;
+                u64::from(u16::from_be_bytes(bytes))
+            }
+            127 => {
+                let mut bytes = [0u8; 8];
+                reader.read_exact(&mut bytes)?;
+                u64::from_be_bytes(bytes)
+            }
+            length => u64::from(length),
+        };
+        if length > MAX_FRAME_BYTES || (opcode >= OPCODE_CLOSE && length > 125) {
+            return Err(invalid("WebSocket frame too large"));
+        }
+
+        let mut mask = [0u8; 4];
+        if masked {
+            reader.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0u8; length as usize];
+        reader.read_exact(&mut payload)?;
+        if masked {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        match opcode {
+            OPCODE_CLOSE => {
+                let code = &payload[..payload.len().min(2)];
+                return send_frame(&mut *writer.lock(), OPCODE_CLOSE, code);
+            }
+            OPCODE_PING => send_frame(&mut *writer.lock(), OPCODE_PONG, &payload)?,
+            _ => {}
+        }
+    }
+}
+
+/// Writes a single unmasked, unfragmented frame
+fn send_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= 0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// Computes `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`
+fn websocket_accept(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes()))
+}
+
+/// SHA-1, needed only for the WebSocket handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let byte = |i: usize| u32::from(chunk.get(i).copied().unwrap_or(0));
+        let bits = (byte(0) << 16) | (byte(1) << 8) | byte(2);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditEvent, ReasonCode};
+
+    fn test_server() -> SocketAddr {
+        let handler: Handler = Arc::new(|request| match request {
+            Request::Explain { pid } => Response::success(pid, format!("explained {}", pid)),
+            _ => Response::failure("not in this test"),
+        });
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        spawn_at(addr, "secret".to_string(), handler).unwrap()
+    }
+
+    fn send(addr: SocketAddr, head: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(head.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_handshake_hashing() {
+        let digest: String = sha1(b"abc").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(digest, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(base64(b"ab"), "YWI=");
+        // The example from RFC 6455
+        assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(
+            route("GET", "/api/v1/sessions"),
+            Route::Call(Request::ListSessions, View::Full)
+        );
+        assert_eq!(
+            route("DELETE", "/api/v1/exclusions/My%20App.exe"),
+            Route::Call(Request::Exclude { app: "My App.exe".into(), remove: true }, View::Full)
+        );
+        assert_eq!(route("GET", "/api/v1/events"), Route::Events);
+        assert_eq!(route("POST", "/api/v1/sessions"), Route::NotFound);
+        assert_eq!(route("GET", "/api/v1/explain/chrome"), Route::NotFound);
+        let token = generate_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token().unwrap());
+    }
+
+    #[test]
+    fn test_requests_need_the_token() {
+        let addr = test_server();
+
+        let denied = send(addr, "GET /api/v1/explain/5 HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n");
+        assert!(denied.starts_with("HTTP/1.1 401"));
+
+        let allowed = send(
+            addr,
+            "GET /api/v1/explain/5 HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n",
+        );
+        assert!(allowed.starts_with("HTTP/1.1 200"));
+        assert!(allowed.ends_with("\"text\":\"explained 5\"}"));
+
+        let query = send(addr, "GET /api/v1/explain/6?token=secret HTTP/1.1\r\n\r\n");
+        assert!(query.starts_with("HTTP/1.1 200"));
+
+        let rebound = send(
+            addr,
+            "GET /api/v1/status?token=secret HTTP/1.1\r\nHost: evil.test\r\n\r\n",
+        );
+        assert!(rebound.starts_with("HTTP/1.1 403"));
+        assert!(send(addr, "GET /nope?token=secret HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_websocket_pushes_events() {
+        let addr = test_server();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"GET /api/v1/events?token=secret HTTP/1.1\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            reader.read_line(&mut head).unwrap();
+        }
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        let event =
+            AuditEvent::new(3, "game.exe", AuditAction::Muted, ReasonCode::Background, None);
+        events::publish(event.into());
+
+        // Other tests publish too, so read until our event shows up
+        loop {
+            let mut header = [0u8; 2];
+            reader.read_exact(&mut header).unwrap();
+            assert_eq!(header[0], 0x80 | OPCODE_TEXT);
+            let mut length = usize::from(header[1]);
+            if length == 126 {
+                let mut extended = [0u8; 2];
+                reader.read_exact(&mut extended).unwrap();
+                length = usize::from(u16::from_be_bytes(extended));
+            }
+            let mut payload = vec![0u8; length];
+            reader.read_exact(&mut payload).unwrap();
+            let text = String::from_utf8(payload).unwrap();
+            if text.contains("\"pid\":3,") {
+                assert!(text.starts_with("{\"event\":\"app-muted\""));
+                break;
+            }
+        }
+    }
+}
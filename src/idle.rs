@@ -0,0 +1,153 @@
+//! User presence detection module
+//! Tracks workstation lock state and input idle time so the engine can
+//! silence everything while the user is away.
+
+use crate::wide::to_wide_null;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HMODULE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::RemoteDesktop::{
+    WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, RegisterClassW, UnregisterClassW, HWND_MESSAGE,
+    WINDOW_EX_STYLE, WINDOW_STYLE, WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK,
+    WTS_SESSION_UNLOCK,
+};
+
+const CLASS_NAME: &str = "BgMuterSessionMonitor";
+
+/// Set by the session monitor window while the workstation is locked
+static SESSION_LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Receives WTS session notifications on a hidden message-only window.
+///
+/// Must be created on a thread that pumps messages (the tray thread);
+/// the lock state it records can then be read from any thread.
+pub struct SessionMonitor {
+    hwnd: HWND,
+    hmodule: HMODULE,
+    class_name: Vec<u16>,
+}
+
+impl SessionMonitor {
+    /// Creates the monitor window and subscribes to session notifications
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let class_name = to_wide_null(CLASS_NAME);
+
+        unsafe {
+            let hmodule = GetModuleHandleW(None)?;
+
+            let wc = WNDCLASSW {
+                lpfnWndProc: Some(session_window_proc),
+                hInstance: hmodule.into(),
+                lpszClassName: PCWSTR(class_name.as_ptr()),
+                ..Default::default()
+            };
+            RegisterClassW(&wc);
+
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE(0),
+                PCWSTR(class_name.as_ptr()),
+                PCWSTR::null(),
+                WINDOW_STYLE(0),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                hmodule,
+                None,
+            )?;
+
+            if let Err(e) = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) {
+                let _ = DestroyWindow(hwnd);
+                let _ = UnregisterClassW(PCWSTR(class_name.as_ptr()), hmodule);
+                return Err(e.into());
+            }
+
+            Ok(Self {
+                hwnd,
+                hmodule,
+                class_name,
+            })
+        }
+    }
+}
+
+impl Drop for SessionMonitor {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = WTSUnRegisterSessionNotification(self.hwnd);
+            let _ = DestroyWindow(self.hwnd);
+            let _ = UnregisterClassW(PCWSTR(self.class_name.as_ptr()), self.hmodule);
+        }
+        SESSION_LOCKED.store(false, Ordering::SeqCst);
+    }
+}
+
+unsafe extern "system" fn session_window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_WTSSESSION_CHANGE {
+        match wparam.0 as u32 {
+            WTS_SESSION_LOCK => {
+                SESSION_LOCKED.store(true, Ordering::SeqCst);
+                log::info!("Session locked");
+            }
+            WTS_SESSION_UNLOCK => {
+                SESSION_LOCKED.store(false, Ordering::SeqCst);
+                log::info!("Session unlocked");
+            }
+            _ => {}
+        }
+        return LRESULT(0);
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+/// Returns true while the workstation is locked
+/// (always false if no SessionMonitor is running)
+pub fn is_session_locked() -> bool {
+    SESSION_LOCKED.load(Ordering::Relaxed)
+}
+
+/// Gets the time since the last keyboard or mouse input in this session
+pub fn idle_duration() -> Duration {
+    unsafe {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if !GetLastInputInfo(&mut info).as_bool() {
+            return Duration::ZERO;
+        }
+
+        // Both values are 32-bit tick counts, so wrap-around is harmless here
+        let idle_ms = GetTickCount().wrapping_sub(info.dwTime);
+        Duration::from_millis(idle_ms as u64)
+    }
+}
+
+/// Decides whether the user counts as away
+///
+/// `idle_threshold` of `None` disables the idle check (lock only).
+pub fn is_user_away(mute_on_lock: bool, idle_threshold: Option<Duration>) -> bool {
+    if mute_on_lock && is_session_locked() {
+        return true;
+    }
+
+    match idle_threshold {
+        Some(threshold) => idle_duration() >= threshold,
+        None => false,
+    }
+}
@@ -0,0 +1,100 @@
+//! Single instance module
+//! Keeps one tray instance per user session. A later launch hands its
+//! command-line arguments to the running instance and exits.
+
+use crate::control::{Request, Response};
+use crate::ipc;
+use crate::tray::TrayCommand;
+use crate::wide::to_wide_null;
+use std::io;
+use std::thread;
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE};
+use windows::Win32::System::Threading::CreateMutexW;
+
+/// How long a second launch waits for a just-started instance to listen
+const FORWARD_ATTEMPTS: u32 = 20;
+const FORWARD_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Launch flags that only apply when starting an instance
+const IGNORED_FLAGS: [&str; 2] = ["--config", "--set"];
+
+/// Held by the running instance; released when dropped
+pub struct InstanceGuard(Option<HANDLE>);
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0 {
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+        }
+    }
+}
+
+/// Gets the tray command for a launch argument the running instance acts on
+pub fn command_for_flag(flag: &str) -> Option<TrayCommand> {
+    match flag {
+        "--settings" => Some(TrayCommand::OpenSettings),
+        "--toggle" => Some(TrayCommand::ToggleMuting),
+        "--recent-activity" => Some(TrayCommand::ShowRecentActivity),
+        _ => None,
+    }
+}
+
+/// Claims the per-session mutex, or returns None if another instance has it
+pub fn acquire() -> Option<InstanceGuard> {
+    let user = std::env::var("USERNAME").unwrap_or_default().to_lowercase();
+    let name = to_wide_null(&format!("Local\\rust-bg-muter-{}", user));
+    unsafe {
+        match CreateMutexW(None, false, PCWSTR(name.as_ptr())) {
+            Ok(handle) if GetLastError() == ERROR_ALREADY_EXISTS => {
+                let _ = CloseHandle(handle);
+                None
+            }
+            Ok(handle) => Some(InstanceGuard(Some(handle))),
+            Err(e) => {
+                // Better two instances than none
+                log::warn!("Failed to create the single-instance mutex: {}", e);
+                Some(InstanceGuard(None))
+            }
+        }
+    }
+}
+
+/// Hands the forwardable arguments to the running instance
+///
+/// Retries for a few seconds, since the other instance may still be starting.
+pub fn forward_args(args: &[String]) -> io::Result<Response> {
+    let ignored: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| IGNORED_FLAGS.contains(arg))
+        .collect();
+    if !ignored.is_empty() {
+        log::warn!(
+            "The running instance keeps its own settings; ignoring {}",
+            ignored.join(" ")
+        );
+    }
+
+    let args: Vec<String> = args
+        .iter()
+        .filter(|arg| command_for_flag(arg).is_some())
+        .cloned()
+        .collect();
+    let request = Request::Activate { args };
+
+    let mut attempt = 1;
+    loop {
+        match ipc::send_request(&request) {
+            Err(e) if attempt < FORWARD_ATTEMPTS => {
+                log::debug!("Running instance not reachable yet: {}", e);
+                attempt += 1;
+                thread::sleep(FORWARD_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
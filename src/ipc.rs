@@ -0,0 +1,553 @@
+//! Local IPC module
+//! Lets other processes (such as `bg-muter ctl`) talk to the running instance
+//! over a named pipe on Windows or a Unix domain socket elsewhere. Only the
+//! current user can connect.
+//!
+//! Protocol (version 1): line-delimited JSON. Each request is an object with
+//! `"v"` (optional, defaults to 1) and `"cmd"`; each response carries `"v"`,
+//! `"ok"`, `"data"`, `"text"` and, on failure, `"error"`. After a successful
+//! `{"cmd": "subscribe"}` the connection only carries events, one per line.
+
+use crate::control::{ControlContext, Request, Response};
+use crate::events::{self, Event};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(windows)]
+use std::fs::{File, OpenOptions};
+#[cfg(windows)]
+use std::os::windows::io::{FromRawHandle, RawHandle};
+#[cfg(windows)]
+use crate::wide::to_wide_null;
+#[cfg(windows)]
+use windows::core::{PCWSTR, PWSTR};
+#[cfg(windows)]
+use windows::Win32::Foundation::{
+    CloseHandle, LocalFree, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL, INVALID_HANDLE_VALUE,
+};
+#[cfg(windows)]
+use windows::Win32::Security::Authorization::{
+    ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+};
+#[cfg(windows)]
+use windows::Win32::Security::{
+    GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY,
+    TOKEN_USER,
+};
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+#[cfg(windows)]
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+    PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+#[cfg(windows)]
+use windows::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+#[cfg(windows)]
+use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentProcessId, OpenProcessToken};
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// The protocol version this build speaks
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A connection to or from the server
+#[cfg(windows)]
+pub type Stream = File;
+#[cfg(unix)]
+pub type Stream = UnixStream;
+
+/// Answers requests on behalf of the server
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// Windows error while every pipe instance is busy
+#[cfg(windows)]
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// Pipe buffer size; requests and responses are small
+#[cfg(windows)]
+const PIPE_BUFFER_SIZE: u32 = 64 * 1024;
+
+/// A message on the wire: the body plus the protocol version
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    #[serde(default = "first_version")]
+    v: u32,
+    #[serde(flatten)]
+    body: T,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+fn current_user() -> String {
+    let name = if cfg!(windows) { "USERNAME" } else { "USER" };
+    std::env::var(name).unwrap_or_default().to_lowercase()
+}
+
+/// Gets the Remote Desktop session this process runs in
+#[cfg(windows)]
+fn current_session() -> u32 {
+    let mut session = 0;
+    unsafe {
+        let _ = ProcessIdToSessionId(GetCurrentProcessId(), &mut session);
+    }
+    session
+}
+
+/// Gets the endpoint for the current user and session, e.g.
+/// `\\.\pipe\rust-bg-muter-alice-1`; one per session, like the instance mutex
+#[cfg(windows)]
+pub fn endpoint() -> PathBuf {
+    PathBuf::from(format!(
+        r"\\.\pipe\rust-bg-muter-{}-{}",
+        current_user(),
+        current_session()
+    ))
+}
+
+/// Gets the endpoint for the current user, in the private runtime directory
+/// if there is one
+#[cfg(unix)]
+pub fn endpoint() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("rust-bg-muter-{}.sock", current_user()))
+}
+
+/// Starts answering control requests on background threads
+pub fn spawn_server(context: ControlContext) -> io::Result<()> {
+    spawn_server_at(&endpoint(), Arc::new(move |request| context.execute(request)))
+}
+
+/// Starts a server at `endpoint`; fails if another server already listens there
+pub fn spawn_server_at(endpoint: &Path, handler: Handler) -> io::Result<()> {
+    let mut listener = Listener::bind(endpoint)?;
+    log::info!("Listening for control requests on {}", endpoint.display());
+
+    thread::Builder::new()
+        .name("ipc-server".to_string())
+        .spawn(move || loop {
+            match listener.accept() {
+                Ok(stream) => {
+                    let handler = handler.clone();
+                    let spawned = thread::Builder::new()
+                        .name("ipc-connection".to_string())
+                        .spawn(move || {
+                            if let Err(e) = serve_connection(stream, &handler) {
+                                log::debug!("Control connection ended: {}", e);
+                            }
+                        });
+                    if let Err(e) = spawned {
+                        log::warn!("Failed to start a control connection thread: {}", e);
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Control server error: {}", e);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Answers requests on a connection until the client hangs up
+fn serve_connection(stream: Stream, handler: &Handler) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                write_message(&mut writer, &Response::failure(e))?;
+                continue;
+            }
+        };
+        log::debug!("Control request: {:?}", request);
+
+        if request == Request::Subscribe {
+            let events = events::subscribe();
+            write_message(&mut writer, &Response::success((), "Subscribed"))?;
+            // Ends when the client goes away and the next write fails
+            for event in events {
+                write_message(&mut writer, &event)?;
+            }
+            return Ok(());
+        }
+        write_message(&mut writer, &handler(&request))?;
+    }
+    Ok(())
+}
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    let envelope: Envelope<Request> =
+        serde_json::from_str(line).map_err(|e| format!("invalid request: {}", e))?;
+    if envelope.v > PROTOCOL_VERSION {
+        return Err(format!(
+            "protocol version {} is not supported (this instance speaks {})",
+            envelope.v, PROTOCOL_VERSION
+        ));
+    }
+    Ok(envelope.body)
+}
+
+fn write_message(stream: &mut impl Write, body: &impl Serialize) -> io::Result<()> {
+    let envelope = Envelope {
+        v: PROTOCOL_VERSION,
+        body,
+    };
+    let mut text = serde_json::to_string(&envelope)?;
+    text.push('\n');
+    stream.write_all(text.as_bytes())?;
+    stream.flush()
+}
+
+fn read_message<T: serde::de::DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<T> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    let envelope: Envelope<T> = serde_json::from_str(&line)?;
+    Ok(envelope.body)
+}
+
+/// A connection to the running instance
+pub struct Client {
+    reader: BufReader<Stream>,
+    writer: Stream,
+}
+
+impl Client {
+    /// Connects to the server at `endpoint`
+    pub fn connect(endpoint: &Path) -> io::Result<Self> {
+        let stream = connect_stream(endpoint)?;
+        Ok(Self {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Sends a request and waits for the answer
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        write_message(&mut self.writer, request)?;
+        read_message(&mut self.reader)
+    }
+
+    /// Subscribes to events and returns them as they arrive
+    pub fn subscribe(mut self) -> io::Result<impl Iterator<Item = io::Result<Event>>> {
+        let response = self.request(&Request::Subscribe)?;
+        if !response.ok {
+            return Err(io::Error::other(response.text));
+        }
+        let mut reader = self.reader;
+        Ok(std::iter::from_fn(move || match read_message(&mut reader) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            result => Some(result),
+        }))
+    }
+}
+
+/// Sends a single request to the running instance
+pub fn send_request(request: &Request) -> io::Result<Response> {
+    Client::connect(&endpoint())?.request(request)
+}
+
+/// Listens for connections on a named pipe that only the current user may open
+#[cfg(windows)]
+struct Listener {
+    name: Vec<u16>,
+    security: PSECURITY_DESCRIPTOR,
+    /// The instance the next client connects to
+    pending: HANDLE,
+}
+
+// The handles are only used from the server thread
+#[cfg(windows)]
+unsafe impl Send for Listener {}
+
+#[cfg(windows)]
+impl Listener {
+    fn bind(endpoint: &Path) -> io::Result<Self> {
+        let sddl = format!("D:P(A;;GA;;;{})", unsafe { current_user_sid()? });
+        let sddl = to_wide_null(&sddl);
+        let mut security = PSECURITY_DESCRIPTOR::default();
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                PCWSTR(sddl.as_ptr()),
+                SDDL_REVISION_1,
+                &mut security,
+                None,
+            )?;
+        }
+
+        let mut listener = Self {
+            name: to_wide_null(&endpoint.to_string_lossy()),
+            security,
+            pending: INVALID_HANDLE_VALUE,
+        };
+        // Refuse to share the name, so nobody else can squat on it
+        listener.pending = listener.create_instance(true)?;
+        Ok(listener)
+    }
+
+    fn create_instance(&self, first: bool) -> io::Result<HANDLE> {
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.security.0,
+            bInheritHandle: false.into(),
+        };
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+
+        let handle = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(self.name.as_ptr()),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                PIPE_BUFFER_SIZE,
+                PIPE_BUFFER_SIZE,
+                0,
+                Some(&attributes),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(handle)
+    }
+
+    /// Waits for a client on the pending instance
+    fn accept(&mut self) -> io::Result<Stream> {
+        if self.pending == INVALID_HANDLE_VALUE {
+            self.pending = self.create_instance(false)?;
+        }
+        let handle = std::mem::replace(&mut self.pending, INVALID_HANDLE_VALUE);
+        // Owned from here on, so the handle is closed on every path
+        let pipe = unsafe { File::from_raw_handle(handle.0 as RawHandle) };
+
+        if let Err(e) = unsafe { ConnectNamedPipe(handle, None) } {
+            // The client may connect between creation and ConnectNamedPipe
+            if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                return Err(e.into());
+            }
+        }
+
+        // Keep an instance listening while this client is served
+        match self.create_instance(false) {
+            Ok(next) => self.pending = next,
+            Err(e) => log::warn!("Failed to create the next pipe instance: {}", e),
+        }
+        Ok(pipe)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        unsafe {
+            if self.pending != INVALID_HANDLE_VALUE {
+                let _ = CloseHandle(self.pending);
+            }
+            LocalFree(HLOCAL(self.security.0));
+        }
+    }
+}
+
+/// Gets the current user's SID in string form, e.g. `S-1-5-21-...`
+#[cfg(windows)]
+unsafe fn current_user_sid() -> io::Result<String> {
+    let mut token = HANDLE::default();
+    OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+
+    let mut length = 0;
+    let _ = GetTokenInformation(token, TokenUser, None, 0, &mut length);
+    // u64s keep the TOKEN_USER inside suitably aligned
+    let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+    let result = GetTokenInformation(
+        token,
+        TokenUser,
+        Some(buffer.as_mut_ptr().cast()),
+        length,
+        &mut length,
+    );
+    let _ = CloseHandle(token);
+    result?;
+
+    let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+    let mut sid = PWSTR::null();
+    ConvertSidToStringSidW(user.User.Sid, &mut sid)?;
+    let text = sid.to_string();
+    LocalFree(HLOCAL(sid.0.cast()));
+    text.map_err(io::Error::other)
+}
+
+/// Opens the pipe, waiting briefly if the server is between instances
+#[cfg(windows)]
+fn connect_stream(endpoint: &Path) -> io::Result<Stream> {
+    let mut attempts = 0;
+    loop {
+        match OpenOptions::new().read(true).write(true).open(endpoint) {
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && attempts < 20 => {
+                attempts += 1;
+                thread::sleep(Duration::from_millis(50));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Listens on a socket file only the current user may open
+#[cfg(unix)]
+struct Listener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Listener {
+    fn bind(endpoint: &Path) -> io::Result<Self> {
+        if UnixStream::connect(endpoint).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another server is listening there",
+            ));
+        }
+        // A socket left behind by a crash would make bind fail
+        let is_socket = std::fs::symlink_metadata(endpoint)
+            .map(|m| m.file_type().is_socket())
+            .unwrap_or(false);
+        if is_socket {
+            std::fs::remove_file(endpoint)?;
+        }
+
+        let listener = UnixListener::bind(endpoint)?;
+        std::fs::set_permissions(endpoint, std::fs::Permissions::from_mode(0o600))?;
+        Ok(Self {
+            listener,
+            path: endpoint.to_path_buf(),
+        })
+    }
+
+    fn accept(&mut self) -> io::Result<Stream> {
+        self.listener.accept().map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn connect_stream(endpoint: &Path) -> io::Result<Stream> {
+    UnixStream::connect(endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditEvent, ReasonCode};
+
+    /// A pipe of the test's own, so no running instance is needed
+    #[cfg(windows)]
+    fn test_endpoint(name: &str) -> PathBuf {
+        PathBuf::from(format!(r"\\.\pipe\bg-muter-test-{}-{}", name, std::process::id()))
+    }
+
+    /// A socket in a temp dir of the test's own
+    #[cfg(unix)]
+    fn test_endpoint(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("bg-muter-ipc-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("ctl.sock")
+    }
+
+    fn test_server(name: &str) -> PathBuf {
+        let path = test_endpoint(name);
+        let handler: Handler = Arc::new(|request| match request {
+            Request::Explain { pid } => Response::success(pid, format!("explained {}", pid)),
+            _ => Response::failure("not in this test"),
+        });
+        spawn_server_at(&path, handler).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_request_response() {
+        let path = test_server("request");
+        let mut client = Client::connect(&path).unwrap();
+
+        let response = client.request(&Request::Explain { pid: 42 }).unwrap();
+        assert!(response.ok);
+        assert_eq!(response.text, "explained 42");
+        // Connections stay open for further requests
+        assert!(!client.request(&Request::Status).unwrap().ok);
+
+        // Other clients are served while the first one is still connected
+        let mut second = Client::connect(&path).unwrap();
+        assert!(second.request(&Request::Explain { pid: 7 }).unwrap().ok);
+
+        // A second server can't take over the endpoint
+        assert!(spawn_server_at(&path, Arc::new(|_| Response::failure("no"))).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = test_server("private");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let path = test_server("protocol");
+        let mut stream = connect_stream(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        stream.write_all(b"{\"v\": 2, \"cmd\": \"status\"}\nnot json\n").unwrap();
+        let newer: Response = read_message(&mut reader).unwrap();
+        assert!(newer.error.unwrap().contains("protocol version 2"));
+        let garbage: Response = read_message(&mut reader).unwrap();
+        assert!(garbage.error.unwrap().starts_with("invalid request"));
+
+        stream.write_all(b"{\"cmd\": \"explain\", \"pid\": 7}\n").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("{\"v\":1,\"ok\":true"));
+    }
+
+    #[test]
+    fn test_subscribe_streams_events() {
+        let path = test_server("subscribe");
+        let mut events = Client::connect(&path).unwrap().subscribe().unwrap();
+
+        let event =
+            AuditEvent::new(9, "game.exe", AuditAction::Muted, ReasonCode::Background, None);
+        events::publish(event.clone().into());
+
+        let expected = Event::AppMuted(event);
+        assert!(events.any(|e| e.unwrap() == expected));
+    }
+}
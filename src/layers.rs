@@ -0,0 +1,392 @@
+//! Layered configuration module
+//! Builds the effective config from built-in defaults, an optional machine or
+//! team policy file, the user's config.json and command-line/environment
+//! overrides. Only the user's own layer is written back to config.json.
+
+use crate::config::Config;
+use crate::migrations;
+use parking_lot::Mutex;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Environment variable that points at the policy file
+pub const POLICY_ENV_VAR: &str = "BG_MUTER_POLICY";
+
+/// `BG_MUTER_<KEY>=<value>` overrides a single config key for this run
+const OVERRIDE_ENV_PREFIX: &str = "BG_MUTER_";
+
+/// Policy key listing the keys users may not change
+const LOCKED_KEY: &str = "locked";
+
+static LAYERS: OnceLock<Layers> = OnceLock::new();
+
+/// The layers above and below the user's config.json
+#[derive(Debug, Default)]
+pub struct Layers {
+    /// Policy values: app lists add to the user's, other keys are defaults
+    policy: Map<String, Value>,
+    /// Keys whose policy value always wins
+    locked: HashSet<String>,
+    /// Values for this run only, never saved
+    overrides: Map<String, Value>,
+    /// The user's own values for locked or overridden keys, saved unchanged
+    user_values: Mutex<Map<String, Value>>,
+}
+
+impl Layers {
+    /// Builds the layers from a policy document and `(key, value)` overrides
+    ///
+    /// Unknown keys are dropped with a warning; policy values of the wrong type
+    /// are an error so a typo can't silently unlock a setting. Overrides of
+    /// locked keys and overrides with bad values are dropped with a warning.
+    pub fn new(policy: Value, overrides: Vec<(String, Value)>) -> Result<Self, String> {
+        let mut policy = match policy {
+            Value::Object(map) => Value::Object(map),
+            Value::Null => Value::Object(Map::new()),
+            _ => return Err("the policy must be a JSON object".to_string()),
+        };
+
+        let locked = match policy.as_object_mut().and_then(|p| p.remove(LOCKED_KEY)) {
+            None => HashSet::new(),
+            Some(keys) => serde_json::from_value::<HashSet<String>>(keys)
+                .map_err(|e| format!("\"{}\" must be a list of keys: {}", LOCKED_KEY, e))?,
+        };
+        migrations::migrate(&mut policy)?;
+
+        let known = known_keys();
+        let mut policy = match policy {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        policy.remove("schema_version");
+        policy.retain(|key, _| {
+            let ok = known.contains(key);
+            if !ok {
+                log::warn!("Ignoring unknown policy key {:?}", key);
+            }
+            ok
+        });
+        for key in &locked {
+            if !policy.contains_key(key) {
+                return Err(format!("locked key {:?} has no policy value", key));
+            }
+        }
+
+        let mut layers = Self {
+            policy,
+            locked,
+            ..Self::default()
+        };
+        layers.check(|| "the policy".to_string())?;
+
+        for (key, value) in overrides {
+            if !known.contains(&key) {
+                log::warn!("Ignoring override of unknown key {:?}", key);
+                continue;
+            }
+            if layers.locked.contains(&key) {
+                log::warn!("Ignoring override of {:?}: the policy locks it", key);
+                continue;
+            }
+            let previous = layers.overrides.insert(key.clone(), value);
+            if let Err(e) = layers.check(|| format!("the override of {:?}", key)) {
+                log::warn!("Ignoring {}", e);
+                match previous {
+                    Some(previous) => layers.overrides.insert(key, previous),
+                    None => layers.overrides.remove(&key),
+                };
+            }
+        }
+        Ok(layers)
+    }
+
+    /// Checks that the layers produce a valid config on top of the defaults
+    fn check(&self, what: impl Fn() -> String) -> Result<(), String> {
+        let mut doc = Value::Object(Map::new());
+        self.apply(&mut doc);
+        serde_json::from_value::<Config>(doc)
+            .map(|_| ())
+            .map_err(|e| format!("{} is invalid: {}", what(), e))
+    }
+
+    /// Whether `key` can't be changed by the user right now
+    pub fn is_locked(&self, key: &str) -> bool {
+        self.locked.contains(key) || self.overrides.contains_key(key)
+    }
+
+    /// Whether the policy puts `item` on the app list `key`
+    pub fn policy_contains(&self, key: &str, item: &str) -> bool {
+        match self.policy.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|i| i.eq_ignore_ascii_case(item)),
+            _ => false,
+        }
+    }
+
+    /// Whether any policy or override is in effect
+    pub fn is_empty(&self) -> bool {
+        self.policy.is_empty() && self.overrides.is_empty()
+    }
+
+    /// Remembers the user's values for keys they can't change, so saving
+    /// doesn't replace them with the policy's or an override's
+    pub fn remember_user_values(&self, user: &Value) {
+        let Some(user) = user.as_object() else {
+            return;
+        };
+        let mut values = self.user_values.lock();
+        values.clear();
+        for (key, value) in user {
+            if self.is_locked(key) {
+                values.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Turns a user document into the effective document
+    pub fn apply(&self, doc: &mut Value) {
+        let Some(doc) = doc.as_object_mut() else {
+            return;
+        };
+
+        for (key, policy_value) in &self.policy {
+            if self.locked.contains(key) {
+                doc.insert(key.clone(), policy_value.clone());
+                continue;
+            }
+            match (doc.get_mut(key), policy_value) {
+                // Policy app lists are a baseline the user adds to
+                (Some(Value::Array(items)), Value::Array(required)) => {
+                    for item in required {
+                        if !items.contains(item) {
+                            items.push(item.clone());
+                        }
+                    }
+                }
+                (Some(_), _) => {}
+                (None, _) => {
+                    doc.insert(key.clone(), policy_value.clone());
+                }
+            }
+        }
+
+        for (key, value) in &self.overrides {
+            doc.insert(key.clone(), value.clone());
+        }
+    }
+
+    /// Turns the effective document back into the user's own layer
+    pub fn to_user_document(&self, doc: &mut Value) {
+        let Some(doc) = doc.as_object_mut() else {
+            return;
+        };
+
+        let user_values = self.user_values.lock();
+        let fixed = self.locked.iter().chain(self.overrides.keys());
+        for key in fixed {
+            match user_values.get(key) {
+                Some(value) => doc.insert(key.clone(), value.clone()),
+                None => doc.remove(key),
+            };
+        }
+
+        for (key, policy_value) in &self.policy {
+            if self.is_locked(key) {
+                continue;
+            }
+            match (doc.get_mut(key), policy_value) {
+                (Some(Value::Array(items)), Value::Array(required)) => {
+                    items.retain(|item| !required.contains(item));
+                }
+                // Unchanged policy defaults keep following the policy
+                (Some(value), _) if value == policy_value => {
+                    doc.remove(key);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The config keys a policy or override may set
+fn known_keys() -> HashSet<String> {
+    match serde_json::to_value(Config::default()) {
+        Ok(Value::Object(map)) => map
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| key != "schema_version")
+            .collect(),
+        _ => HashSet::new(),
+    }
+}
+
+/// Gets the policy file path: `BG_MUTER_POLICY`, or
+/// `%PROGRAMDATA%\rust-bg-muter\policy.json`
+pub fn policy_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(POLICY_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("ProgramData")
+        .map(|dir| PathBuf::from(dir).join("rust-bg-muter").join("policy.json"))
+}
+
+/// Parses an override value: JSON if it parses, otherwise a plain string
+pub fn parse_override_value(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+/// Reads `BG_MUTER_<KEY>` overrides from the environment
+fn env_overrides() -> Vec<(String, Value)> {
+    let known = known_keys();
+    std::env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(OVERRIDE_ENV_PREFIX)?.to_lowercase();
+            known
+                .contains(&key)
+                .then(|| (key, parse_override_value(&value)))
+        })
+        .collect()
+}
+
+/// Loads the policy file and overrides; must run before the config is loaded
+///
+/// `cli_overrides` come from `--set key=value` and win over the environment.
+/// A broken policy is reported and ignored rather than stopping the app.
+pub fn init(cli_overrides: Vec<(String, Value)>) {
+    let policy = match policy_path() {
+        Some(path) if path.exists() => match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(policy) => {
+                    log::info!("Using policy file {:?}", path);
+                    policy
+                }
+                Err(e) => {
+                    log::error!("Ignoring unparsable policy file {:?}: {}", path, e);
+                    Value::Null
+                }
+            },
+            Err(e) => {
+                log::error!("Failed to read policy file {:?}: {}", path, e);
+                Value::Null
+            }
+        },
+        _ => Value::Null,
+    };
+
+    let mut overrides = env_overrides();
+    overrides.extend(cli_overrides);
+
+    let layers = Layers::new(policy, overrides).unwrap_or_else(|e| {
+        log::error!("Ignoring policy and overrides: {}", e);
+        Layers::default()
+    });
+    let _ = LAYERS.set(layers);
+}
+
+/// Gets the layers for this process (empty if `init` was never called)
+pub fn current() -> &'static Layers {
+    LAYERS.get_or_init(Layers::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn team_policy() -> Layers {
+        let policy = json!({
+            "locked": ["always_muted_apps"],
+            "always_muted_apps": ["softphone.exe"],
+            "excluded_apps": ["teams.exe"],
+            "poll_interval_ms": 250,
+            "not_a_key": true
+        });
+        Layers::new(policy, vec![("dry_run".to_string(), json!(true))]).unwrap()
+    }
+
+    #[test]
+    fn test_apply_layers() {
+        let layers = team_policy();
+        let mut doc = json!({
+            "excluded_apps": ["spotify.exe"],
+            "always_muted_apps": ["game.exe"],
+            "dry_run": false
+        });
+        layers.apply(&mut doc);
+
+        assert_eq!(doc["excluded_apps"], json!(["spotify.exe", "teams.exe"]));
+        assert_eq!(doc["always_muted_apps"], json!(["softphone.exe"]));
+        assert_eq!(doc["poll_interval_ms"], json!(250));
+        assert_eq!(doc["dry_run"], json!(true));
+        assert!(doc.get("not_a_key").is_none());
+
+        assert!(layers.is_locked("always_muted_apps"));
+        assert!(layers.is_locked("dry_run"));
+        assert!(!layers.is_locked("excluded_apps"));
+        assert!(layers.policy_contains("excluded_apps", "Teams.exe"));
+    }
+
+    #[test]
+    fn test_user_document_round_trip() {
+        let layers = team_policy();
+        let user = json!({
+            "excluded_apps": ["spotify.exe"],
+            "always_muted_apps": ["game.exe"],
+            "dry_run": false
+        });
+        layers.remember_user_values(&user);
+
+        let mut doc = user.clone();
+        layers.apply(&mut doc);
+        layers.to_user_document(&mut doc);
+        assert_eq!(doc, user);
+
+        // A changed policy default becomes the user's own value
+        let mut doc = user.clone();
+        layers.apply(&mut doc);
+        doc["poll_interval_ms"] = json!(700);
+        layers.to_user_document(&mut doc);
+        assert_eq!(doc["poll_interval_ms"], json!(700));
+    }
+
+    #[test]
+    fn test_invalid_layers_are_rejected() {
+        assert!(Layers::new(json!({"poll_interval_ms": "fast"}), vec![]).is_err());
+        assert!(Layers::new(json!({"locked": ["muting_enabled"]}), vec![]).is_err());
+        assert!(Layers::new(json!([]), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_bad_overrides_are_dropped() {
+        let overrides = vec![
+            ("dry_run".to_string(), json!(true)),
+            ("poll_interval_ms".to_string(), json!("fast")),
+            ("always_muted_apps".to_string(), json!([])),
+            ("dry_run".to_string(), json!("yes")),
+        ];
+        let policy = json!({
+            "locked": ["always_muted_apps"],
+            "always_muted_apps": ["softphone.exe"]
+        });
+        let layers = Layers::new(policy, overrides).unwrap();
+
+        let mut doc = json!({});
+        layers.apply(&mut doc);
+        assert_eq!(doc["always_muted_apps"], json!(["softphone.exe"]));
+        assert_eq!(doc["dry_run"], json!(true));
+        assert!(doc.get("poll_interval_ms").is_none());
+        assert!(!layers.is_locked("poll_interval_ms"));
+    }
+
+    #[test]
+    fn test_parse_override_value() {
+        assert_eq!(parse_override_value("250"), json!(250));
+        assert_eq!(parse_override_value("true"), json!(true));
+        assert_eq!(parse_override_value("fullscreen"), json!("fullscreen"));
+    }
+}
@@ -4,12 +4,32 @@
 //! applications on Windows with minimal resource usage.
 
 pub mod audio;
+pub mod audit;
+pub mod clock;
 pub mod config;
+pub mod config_format;
+pub mod config_watcher;
+pub mod control;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+pub mod events;
+pub mod hooks;
+pub mod hotkeys;
+pub mod http_api;
+pub mod idle;
+pub mod instance;
+pub mod ipc;
+pub mod layers;
+pub mod migrations;
 pub mod muter;
+pub mod persist;
+pub mod presets;
 pub mod process;
+pub mod scripting;
 pub mod settings_dialog;
 pub mod startup;
 pub mod tray;
+pub mod wide;
 
 pub use audio::AudioManager;
 pub use config::Config;
@@ -6,22 +6,52 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod audio;
+mod audit;
+mod clock;
 mod config;
+mod config_format;
+mod config_watcher;
+mod control;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod events;
+mod hooks;
+mod hotkeys;
+mod http_api;
+mod idle;
+mod instance;
+mod ipc;
+mod layers;
+mod migrations;
 mod muter;
+mod persist;
+mod presets;
 mod process;
+mod scripting;
 mod settings_dialog;
 mod startup;
 mod tray;
+mod wide;
 
 use config::Config;
+use config_watcher::ConfigReloader;
+use hotkeys::{HotkeyAction, HotkeyManager};
 use muter::MuterEngine;
 use parking_lot::RwLock;
+use presets::{ImportMode, Preset};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tray::{SystemTray, TrayCommand};
+use windows::Win32::Foundation::HWND;
 use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED};
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+use windows::Win32::UI::WindowsAndMessaging::{MB_ICONINFORMATION, MB_ICONWARNING};
+
+/// Number of audit events shown by the tray's Recent Activity item
+const RECENT_ACTIVITY_LIMIT: usize = 20;
 
 /// Application entry point
 fn main() {
@@ -33,7 +63,64 @@ fn main() {
             .init();
     }
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // `bg-muter ctl <command>` talks to the running instance and exits
+    if args.first().map(String::as_str) == Some("ctl") {
+        attach_parent_console();
+        std::process::exit(control::run_ctl(&args[1..]));
+    }
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--config") {
+        let Some(path) = args.get(pos + 1) else {
+            attach_parent_console();
+            eprintln!("Usage: bg-muter --config <path>");
+            std::process::exit(2);
+        };
+        Config::set_config_path(PathBuf::from(path));
+    }
+
+    // --set key=value overrides a config key for this run only
+    let mut overrides = Vec::new();
+    for (i, _) in args.iter().enumerate().filter(|(_, arg)| *arg == "--set") {
+        let Some((key, value)) = args.get(i + 1).and_then(|a| a.split_once('=')) else {
+            attach_parent_console();
+            eprintln!("Usage: bg-muter --set <key>=<value>");
+            std::process::exit(2);
+        };
+        overrides.push((key.trim().to_string(), layers::parse_override_value(value.trim())));
+    }
+    layers::init(overrides);
+
+    // Command-line queries print their answer and exit
+    if let Some(pos) = args.iter().position(|arg| arg == "--explain") {
+        std::process::exit(explain_from_command_line(args.get(pos + 1)));
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--export-preset") {
+        let name = args.iter().position(|arg| arg == "--name").and_then(|i| args.get(i + 1));
+        std::process::exit(export_preset_from_command_line(args.get(pos + 1), name));
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--import-preset") {
+        let mode = if args.iter().any(|arg| arg == "--replace") {
+            ImportMode::Replace
+        } else {
+            ImportMode::Merge
+        };
+        std::process::exit(import_preset_from_command_line(args.get(pos + 1), mode));
+    }
+
+    // One instance per session; a second launch hands its flags over and exits
+    let Some(_instance) = instance::acquire() else {
+        match instance::forward_args(&args) {
+            Ok(response) => log::info!("{}", response.text),
+            Err(e) => log::warn!("Another instance is running but not answering: {}", e),
+        }
+        return;
+    };
+
     log::info!("Background Muter starting (lightweight mode)...");
+    let (config_path, config_source) = Config::config_location();
+    log::info!("Using config file {:?} ({:?})", config_path, config_source);
 
     // Load configuration
     let config = Arc::new(RwLock::new(Config::load()));
@@ -108,11 +195,14 @@ fn main() {
         let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
     }
 
+    // Flags like --settings act the same on the first launch as when forwarded
+    let startup_commands = args.iter().filter_map(|arg| instance::command_for_flag(arg)).collect();
     run_tray_loop(
         config.clone(),
         engine.clone(),
         should_exit.clone(),
         muting_enabled.clone(),
+        startup_commands,
     );
 
     // Signal exit and wait for muting thread
@@ -124,6 +214,9 @@ fn main() {
         eng.unmute_all();
     }
 
+    // Write any debounced config change before exiting
+    persist::flush();
+
     unsafe {
         CoUninitialize();
     }
@@ -131,12 +224,126 @@ fn main() {
     log::info!("Background Muter shutdown complete");
 }
 
+/// Attaches to the console of the launching shell so println! reaches it
+/// (release builds use the windows subsystem and start without a console)
+fn attach_parent_console() {
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Handles `--explain <pid>`, returning the process exit code
+fn explain_from_command_line(pid: Option<&String>) -> i32 {
+    attach_parent_console();
+
+    let Some(pid) = pid.and_then(|p| p.parse::<u32>().ok()) else {
+        eprintln!("Usage: bg-muter --explain <pid>");
+        return 2;
+    };
+
+    // The running instance knows the foreground, pins, peek and focus history
+    if let Ok(response) = ipc::send_request(&control::Request::Explain { pid }) {
+        if response.ok {
+            println!("{}", response.text);
+            return 0;
+        }
+        eprintln!("Error: {}", response.text);
+        return 1;
+    }
+
+    unsafe {
+        let _ = CoInitializeEx(None, windows::Win32::System::Com::COINIT_MULTITHREADED);
+    }
+
+    let config = Arc::new(RwLock::new(Config::load()));
+    let code = match MuterEngine::new(config) {
+        Ok(mut engine) => {
+            engine.refresh_foreground();
+            println!("{}", engine.explain(pid));
+            println!();
+            println!("Background Muter is not running; evaluated against the saved config");
+            println!("and current windows.");
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to create muter engine: {}", e);
+            1
+        }
+    };
+
+    persist::flush();
+    unsafe {
+        CoUninitialize();
+    }
+    code
+}
+
+/// Handles `--export-preset <path> [--name <name>]`
+fn export_preset_from_command_line(path: Option<&String>, name: Option<&String>) -> i32 {
+    attach_parent_console();
+
+    let Some(path) = path else {
+        eprintln!("Usage: bg-muter --export-preset <path> [--name <name>]");
+        return 2;
+    };
+
+    let preset = Preset::from_config(&Config::load(), name.cloned());
+    persist::flush();
+    match preset.save(Path::new(path)) {
+        Ok(()) => {
+            println!("Exported {} entries to {}", preset.len(), path);
+            0
+        }
+        Err(e) => {
+            eprintln!("Export failed: {}", e);
+            1
+        }
+    }
+}
+
+/// Handles `--import-preset <path> [--replace]`
+///
+/// Writes config.json; a running instance picks the change up by itself.
+fn import_preset_from_command_line(path: Option<&String>, mode: ImportMode) -> i32 {
+    attach_parent_console();
+
+    let Some(path) = path else {
+        eprintln!("Usage: bg-muter --import-preset <path> [--replace]");
+        return 2;
+    };
+
+    let preset = match Preset::load(Path::new(path)) {
+        Ok(preset) => preset,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path, e);
+            return 1;
+        }
+    };
+    if preset.is_empty() {
+        eprintln!("{} has no apps to import", path);
+        return 1;
+    }
+
+    let mut config = Config::load();
+    if let Some(error) = &config.load_error {
+        eprintln!("Not importing into the broken config file ({})", error);
+        return 1;
+    }
+
+    let report = preset.apply_to(&mut config, mode);
+    print!("{}", report);
+    let _ = config.save();
+    persist::flush();
+    0
+}
+
 /// Main tray message loop - blocks until exit
 fn run_tray_loop(
     config: Arc<RwLock<Config>>,
     engine: Arc<RwLock<MuterEngine>>,
     should_exit: Arc<AtomicBool>,
     muting_enabled: Arc<AtomicBool>,
+    startup_commands: Vec<TrayCommand>,
 ) {
     let mut tray = match SystemTray::new(muting_enabled.load(Ordering::Relaxed)) {
         Ok(t) => t,
@@ -147,14 +354,133 @@ fn run_tray_loop(
     };
 
     log::info!("System tray initialized");
+    for command in startup_commands {
+        let _ = tray.command_sender().try_send(command);
+    }
+
+    // Tell the user why their settings are missing instead of failing silently
+    let load_error = config.read().load_error.clone();
+    if let Some(error) = load_error {
+        let backup = match &error.backup {
+            Some(path) => format!("A copy was saved to {}.\n\n", path.display()),
+            None => String::new(),
+        };
+        let text = format!(
+            "config.json could not be loaded ({}).\n\n{}Background Muter is running on default \
+             settings and will not overwrite config.json until you save from Settings. \
+             Fixing the file also works: it is reloaded automatically.",
+            error, backup
+        );
+        settings_dialog::show_message_box(HWND::default(), &text, MB_ICONWARNING);
+    }
+
+    // Lock/unlock notifications are delivered through this thread's message pump
+    let _session_monitor = match idle::SessionMonitor::new() {
+        Ok(m) => Some(m),
+        Err(e) => {
+            log::warn!("Failed to register for session notifications: {}", e);
+            None
+        }
+    };
+
+    // Hotkeys are delivered as thread messages, so register them on this thread
+    let mut hotkeys = HotkeyManager::register(&config.read().hotkeys);
+
+    // External edits to config.json are merged in on this thread
+    let mut reloader = ConfigReloader::new();
+    let mut last_reload_error = None;
+    let reload_tx = tray.command_sender();
+    let watch_result = config_watcher::spawn_watcher(Config::config_path(), move || {
+        let _ = reload_tx.try_send(TrayCommand::ReloadConfig);
+    });
+    if let Err(e) = watch_result {
+        log::warn!("Failed to watch config file: {}", e);
+    }
+
+    // `bg-muter ctl` requests are answered on their own thread
+    let control = control::ControlContext {
+        config: config.clone(),
+        engine: engine.clone(),
+        muting_enabled: muting_enabled.clone(),
+        tray: tray.command_sender(),
+    };
+    if let Err(e) = ipc::spawn_server(control.clone()) {
+        log::warn!("Failed to start the control server: {}", e);
+    }
+
+    // Desktop extensions reach the same requests over the session bus
+    #[cfg(target_os = "linux")]
+    if let Err(e) = dbus::spawn(control.clone()) {
+        log::warn!("Failed to start the D-Bus service: {}", e);
+    }
+
+    // Hooks read the config for each event, so edits apply right away
+    if let Err(e) = hooks::spawn(config.clone()) {
+        log::warn!("Failed to start the hook runner: {}", e);
+    }
+
+    // The HTTP API is opt-in; a token is made up the first time it's enabled
+    let http_api = {
+        let mut cfg = config.write();
+        if cfg.http_api_enabled && cfg.http_api_token.is_empty() {
+            match http_api::generate_token() {
+                Ok(token) => {
+                    cfg.http_api_token = token;
+                    if let Err(e) = cfg.save() {
+                        log::warn!("Failed to save the HTTP API token: {}", e);
+                    }
+                }
+                // An empty token refuses every request
+                Err(e) => log::warn!("Failed to make an HTTP API token: {}", e),
+            }
+        }
+        cfg.http_api_enabled
+            .then(|| (cfg.http_api_port, cfg.http_api_token.clone()))
+    };
+    if let Some((port, token)) = http_api {
+        if let Err(e) = http_api::spawn(control, port, token) {
+            log::warn!("Failed to start the HTTP API on port {}: {}", port, e);
+        }
+    }
+    let mut peek_active = false;
 
     // Message pump with minimal CPU usage
     loop {
         // Process Windows messages (blocking with timeout for efficiency)
-        if !tray.pump_messages(Duration::from_millis(500)) {
+        // While peeking, wake up often enough to notice the key being released
+        let timeout = if peek_active { 50 } else { 500 };
+        if !tray.pump_messages(Duration::from_millis(timeout)) {
             break;
         }
 
+        // Expire snoozes and keep the tooltip countdown current
+        let (snooze_remaining, snooze_expired, dry_run) = {
+            let cfg = config.read();
+            let expired = cfg.snoozed_until.is_some() && !cfg.is_snoozed();
+            (cfg.snooze_remaining(), expired, cfg.dry_run)
+        };
+        if snooze_expired {
+            config.write().cancel_snooze();
+            engine.write().request_refresh();
+            log::info!("Snooze expired, muting resumed");
+        }
+        tray.set_dry_run(dry_run);
+        tray.update_state(muting_enabled.load(Ordering::Relaxed), snooze_remaining);
+
+        // Garbage-collect expired temporary exemptions
+        if config.read().has_expired_exemptions() {
+            for app in config.write().prune_expired_exemptions() {
+                log::info!("Temporary exemption for {} expired", app);
+            }
+            engine.write().request_refresh();
+        }
+
+        if peek_active && !hotkeys.is_held(HotkeyAction::Peek) {
+            peek_active = false;
+            engine.write().set_peek(false);
+            log::info!("Peek released");
+        }
+
         // Check for exit signal from other threads
         if should_exit.load(Ordering::Relaxed) {
             break;
@@ -163,13 +489,13 @@ fn run_tray_loop(
         // Process tray commands
         while let Some(cmd) = tray.poll_command() {
             match cmd {
-                TrayCommand::ToggleMuting => {
+                TrayCommand::ToggleMuting | TrayCommand::Hotkey(HotkeyAction::ToggleMuting) => {
                     let enabled = {
                         let mut cfg = config.write();
                         cfg.toggle_muting()
                     };
-                    muting_enabled.store(enabled, Ordering::SeqCst);
-                    tray.update_state(enabled);
+                    events::store_muting_flag(&muting_enabled, enabled);
+                    tray.update_state(enabled, None);
 
                     // If disabling, unmute everything immediately
                     if !enabled {
@@ -185,12 +511,16 @@ fn run_tray_loop(
                     settings_dialog::open_settings_dialog(
                         config.clone(),
                         muting_enabled.clone(),
+                        engine.clone(),
                     );
                     
                     // Sync muting state after dialog closes (user may have changed it)
-                    let new_enabled = config.read().muting_enabled;
-                    muting_enabled.store(new_enabled, Ordering::SeqCst);
-                    tray.update_state(new_enabled);
+                    let (new_enabled, snooze_remaining) = {
+                        let cfg = config.read();
+                        (cfg.muting_enabled, cfg.snooze_remaining())
+                    };
+                    events::store_muting_flag(&muting_enabled, new_enabled);
+                    tray.update_state(new_enabled, snooze_remaining);
                     
                     // If muting was disabled, unmute everything
                     if !new_enabled {
@@ -199,6 +529,126 @@ fn run_tray_loop(
                         }
                     }
                 }
+                TrayCommand::Snooze(duration) => {
+                    let remaining = {
+                        let mut cfg = config.write();
+                        cfg.snooze_for(duration);
+                        cfg.snooze_remaining()
+                    };
+                    engine.write().request_refresh();
+                    tray.update_state(muting_enabled.load(Ordering::Relaxed), remaining);
+                    log::info!("Muting snoozed for {:?}", duration);
+                }
+                TrayCommand::CancelSnooze => {
+                    config.write().cancel_snooze();
+                    engine.write().request_refresh();
+                    tray.update_state(muting_enabled.load(Ordering::Relaxed), None);
+                    log::info!("Snooze cancelled");
+                }
+                TrayCommand::ExemptCurrent(_) | TrayCommand::ExemptCurrentForToday => {
+                    let app = engine.read().foreground_app();
+                    if let Some((_, name)) = app {
+                        let now = config::unix_now();
+                        let until = match cmd {
+                            TrayCommand::ExemptCurrent(duration) => now + duration.as_secs(),
+                            _ => clock::end_of_local_day(now, clock::local_utc_offset_secs()),
+                        };
+                        config.write().add_temporary_exemption(&name, until);
+                        engine.write().request_refresh();
+                        log::info!("{} exempt for {} s", name, until - now);
+                    }
+                }
+                TrayCommand::ToggleDryRun => {
+                    let dry_run = config.write().toggle_dry_run();
+                    engine.write().request_refresh();
+                    tray.set_dry_run(dry_run);
+                    log::info!("Dry run: {}", dry_run);
+                }
+                TrayCommand::ReloadConfig => {
+                    let (old_hotkeys, old_startup) = {
+                        let cfg = config.read();
+                        (cfg.hotkeys.clone(), cfg.start_with_windows)
+                    };
+
+                    let result = reloader.reload(&config);
+                    if result.is_ok() {
+                        last_reload_error = None;
+                    }
+                    match result {
+                        Ok(true) => {
+                            let cfg = config.read();
+                            events::store_muting_flag(&muting_enabled, cfg.muting_enabled);
+                            tray.update_state(cfg.muting_enabled, cfg.snooze_remaining());
+                            if cfg.hotkeys != old_hotkeys {
+                                hotkeys.reregister(&cfg.hotkeys);
+                            }
+                            if cfg.start_with_windows != old_startup {
+                                if let Err(e) =
+                                    startup::apply_startup_setting(cfg.start_with_windows)
+                                {
+                                    log::warn!("Failed to apply startup setting: {}", e);
+                                }
+                            }
+                            let enabled = cfg.muting_enabled;
+                            drop(cfg);
+
+                            let mut eng = engine.write();
+                            if !enabled {
+                                eng.unmute_all();
+                            }
+                            eng.request_refresh();
+                            log::info!("Config reloaded from disk");
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            log::warn!("Ignoring invalid config.json edit: {}", e);
+                            // Editors save repeatedly; report each distinct error once
+                            if last_reload_error.as_ref() != Some(&e) {
+                                let text = format!(
+                                    "Your edit to config.json was not applied ({}).\n\n\
+                                     The current settings stay in effect until the file is fixed.",
+                                    e
+                                );
+                                settings_dialog::show_message_box(
+                                    HWND::default(),
+                                    &text,
+                                    MB_ICONWARNING,
+                                );
+                                last_reload_error = Some(e);
+                            }
+                        }
+                    }
+                }
+                TrayCommand::ShowRecentActivity => {
+                    let events = engine.read().audit_log().lock().recent(RECENT_ACTIVITY_LIMIT);
+                    let text = if events.is_empty() {
+                        "Nothing has been muted or unmuted yet.".to_string()
+                    } else {
+                        events.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+                    };
+                    settings_dialog::show_message_box(HWND::default(), &text, MB_ICONINFORMATION);
+                }
+                TrayCommand::Hotkey(HotkeyAction::ToggleExcludeCurrent) => {
+                    let app = engine.read().foreground_app();
+                    if let Some((_, name)) = app {
+                        let excluded = config.write().toggle_excluded_app(&name);
+                        engine.write().request_refresh();
+                        log::info!("{} excluded: {}", name, excluded);
+                    }
+                }
+                TrayCommand::Hotkey(HotkeyAction::PinCurrent) => {
+                    match engine.write().toggle_pin_foreground() {
+                        Some((pid, name)) => log::info!("Pinned {} (PID {}) audible", name, pid),
+                        None => log::info!("Pin released"),
+                    }
+                }
+                TrayCommand::Hotkey(HotkeyAction::Peek) => {
+                    if !peek_active {
+                        peek_active = true;
+                        engine.write().set_peek(true);
+                        log::info!("Peek started");
+                    }
+                }
                 TrayCommand::Exit => {
                     should_exit.store(true, Ordering::SeqCst);
                     break;
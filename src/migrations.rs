@@ -0,0 +1,128 @@
+//! Config schema migrations
+//! Upgrades config documents written by older versions one step at a time,
+//! before they are deserialized into `Config`.
+
+use serde_json::{Map, Value};
+
+/// The schema version this build writes
+pub const CURRENT_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1];
+
+/// App lists that hold process names
+const APP_LISTS: [&str; 4] = [
+    "excluded_apps",
+    "always_muted_apps",
+    "away_allowed_apps",
+    "focus_transparent_apps",
+];
+
+/// Gets the schema version of a document (files from before versioning are 0)
+pub fn document_version(doc: &Map<String, Value>) -> u32 {
+    doc.get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// Upgrades a document to `CURRENT_VERSION` in place
+/// Returns the version it started from, or None if it was already current.
+pub fn migrate(doc: &mut Value) -> Result<Option<u32>, String> {
+    let Value::Object(map) = doc else {
+        return Err("the config must be a JSON object".to_string());
+    };
+
+    let version = document_version(map);
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "schema_version {} is newer than this version of Background Muter supports ({})",
+            version, CURRENT_VERSION
+        ));
+    }
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(map);
+        map.insert("schema_version".to_string(), Value::from(from as u32 + 1));
+        log::info!("Migrated config from schema {} to {}", from, from + 1);
+    }
+    Ok(Some(version))
+}
+
+/// v0 -> v1: process names are matched lowercase, so store them that way
+/// (older builds kept hand-edited entries as typed, leaving duplicates)
+fn v0_to_v1(doc: &mut Map<String, Value>) {
+    for key in APP_LISTS {
+        if let Some(Value::Array(apps)) = doc.get_mut(key) {
+            let mut seen = Vec::new();
+            for app in apps.iter().filter_map(Value::as_str) {
+                let app = app.to_lowercase();
+                if !seen.contains(&app) {
+                    seen.push(app);
+                }
+            }
+            *apps = seen.into_iter().map(Value::from).collect();
+        }
+    }
+
+    // Case-only duplicates keep the later deadline
+    if let Some(Value::Object(exemptions)) = doc.get_mut("temporary_exemptions") {
+        let mut merged = Map::new();
+        for (app, until) in exemptions.iter() {
+            let app = app.to_lowercase();
+            let until = until.as_u64().unwrap_or(0);
+            let latest = merged.get(&app).and_then(Value::as_u64).unwrap_or(0).max(until);
+            merged.insert(app, Value::from(latest));
+        }
+        *exemptions = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const V0: &str = include_str!("../tests/fixtures/config_v0.json");
+    const V1: &str = include_str!("../tests/fixtures/config_v1.json");
+
+    #[test]
+    fn test_v0_to_v1_fixture() {
+        let mut doc: Value = serde_json::from_str(V0).unwrap();
+        let expected: Value = serde_json::from_str(V1).unwrap();
+
+        assert_eq!(migrate(&mut doc), Ok(Some(0)));
+        assert_eq!(doc, expected);
+
+        // The result must load as a current config
+        let config: Config = serde_json::from_value(doc).unwrap();
+        assert_eq!(config.schema_version, CURRENT_VERSION);
+        assert_eq!(config.excluded_apps.len(), 2);
+    }
+
+    #[test]
+    fn test_current_version_untouched() {
+        let mut doc: Value = serde_json::from_str(V1).unwrap();
+        let before = doc.clone();
+        assert_eq!(migrate(&mut doc), Ok(None));
+        assert_eq!(doc, before);
+    }
+
+    #[test]
+    fn test_rejects_newer_and_invalid_documents() {
+        let mut newer = serde_json::json!({ "schema_version": CURRENT_VERSION + 1 });
+        assert!(migrate(&mut newer).is_err());
+
+        let mut not_object = serde_json::json!(["spotify.exe"]);
+        assert!(migrate(&mut not_object).is_err());
+    }
+
+    #[test]
+    fn test_every_version_has_a_migration() {
+        assert_eq!(MIGRATIONS.len(), CURRENT_VERSION as usize);
+    }
+}
@@ -2,10 +2,20 @@
 //! Implements the background muting algorithm with minimal overhead
 
 use crate::audio::AudioManager;
-use crate::config::Config;
-use crate::process::get_foreground_pid;
-use parking_lot::RwLock;
+use crate::audit::{AuditAction, AuditEvent, AuditLog, FocusChange, ReasonCode};
+use crate::clock::local_utc_offset_secs;
+use crate::config::{unix_now, AudibilityPolicy, Config};
+use crate::events::{self, Event};
+use crate::idle::is_user_away;
+use crate::process::{
+    get_current_desktop_window_pids, get_foreground_window, get_process_name,
+    get_visible_window_pids, ForegroundWindow,
+};
+use crate::scripting::{PolicyScript, ScriptContext, ScriptSession};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -18,18 +28,33 @@ pub struct AppAudioState {
     pub display_name: String,
     pub is_muted_by_us: bool,
     pub original_mute_state: bool,
+    pub device_name: String,
     pub last_seen: Instant,
     pub is_active: bool,
+    /// A dry-run unmute of a session muted elsewhere was already recorded
+    /// (the mute stays in place, so every refresh would look like a new one)
+    pub dry_run_unmuted: bool,
 }
 
 /// The core muting engine - optimized for minimal CPU usage
 pub struct MuterEngine {
     audio_manager: Arc<AudioManager>,
     config: Arc<RwLock<Config>>,
+    audit: Arc<Mutex<AuditLog>>,
     app_states: HashMap<u32, AppAudioState>,
     muted_pids: HashSet<u32>,
     own_pid: u32,
     last_foreground_pid: Option<u32>,
+    previous_foreground_pid: Option<u32>,
+    foreground_name_cache: Option<(u32, Option<String>)>,
+    pinned_pid: Option<u32>,
+    peek_active: bool,
+    refresh_requested: bool,
+    user_away: bool,
+    dry_run: bool,
+    script: PolicyScript,
+    /// What the policy script decided in the last update
+    script_decisions: HashMap<u32, Decision>,
     last_session_refresh: Instant,
     session_refresh_interval: Duration,
 }
@@ -43,10 +68,20 @@ impl MuterEngine {
         Ok(Self {
             audio_manager,
             config,
+            audit: Arc::new(Mutex::new(AuditLog::default())),
             app_states: HashMap::new(),
             muted_pids: HashSet::new(),
             own_pid: std::process::id(),
             last_foreground_pid: None,
+            previous_foreground_pid: None,
+            foreground_name_cache: None,
+            pinned_pid: None,
+            peek_active: false,
+            refresh_requested: false,
+            user_away: false,
+            dry_run: false,
+            script: PolicyScript::default(),
+            script_decisions: HashMap::new(),
             last_session_refresh: Instant::now(),
             session_refresh_interval: Duration::from_secs(2), // Only refresh sessions every 2s
         })
@@ -57,23 +92,58 @@ impl MuterEngine {
         self.audio_manager.clone()
     }
 
+    /// Gets the audit log of mute/unmute decisions
+    pub fn audit_log(&self) -> Arc<Mutex<AuditLog>> {
+        self.audit.clone()
+    }
+
     /// Updates the engine state and applies muting logic
     /// Optimized: only refreshes audio sessions periodically, not every poll
     pub fn update(&mut self) -> Result<UpdateResult, Box<dyn std::error::Error>> {
+        let mut policy = self.policy_inputs();
         let config = self.config.read();
-        let muting_enabled = config.muting_enabled;
-        let excluded_apps = config.excluded_apps.clone();
-        let always_muted_apps = config.always_muted_apps.clone();
+        let mute_when_away = config.mute_when_away;
+        let mute_on_lock = config.mute_on_lock;
+        let away_idle_threshold = config.away_idle_threshold();
+        let audit_to_file = config.audit_log_to_file;
+        let dry_run = config.dry_run;
+        let script_path = config.policy_script_path();
         drop(config);
 
-        // Get current foreground PID
-        let foreground_pid = get_foreground_pid();
+        // Check user presence (cheap: one atomic load and one GetLastInputInfo call)
+        policy.user_away = mute_when_away && is_user_away(mute_on_lock, away_idle_threshold);
+        let away_changed = policy.user_away != self.user_away;
+        if away_changed {
+            log::info!("User away: {}", policy.user_away);
+            self.user_away = policy.user_away;
+        }
+
+        // Entering dry-run undoes our real mutes; leaving it forgets the simulated ones
+        let dry_run_changed = dry_run != self.dry_run;
+        if dry_run_changed {
+            log::info!("Dry run: {}", dry_run);
+            if dry_run {
+                self.unmute_all();
+            } else {
+                self.forget_mutes();
+            }
+            self.dry_run = dry_run;
+        }
+
+        // Get current foreground PID (focus-transparent windows keep the previous one)
+        let foreground_pid = self.resolve_foreground(&policy);
         let previous_foreground_pid = self.last_foreground_pid;
         let foreground_changed = foreground_pid != previous_foreground_pid;
+        if foreground_changed {
+            self.previous_foreground_pid = previous_foreground_pid;
+        }
         self.last_foreground_pid = foreground_pid;
 
-        // Only refresh audio sessions periodically OR when foreground changes
+        // Only refresh audio sessions periodically OR when foreground/presence changes
         let should_refresh = foreground_changed
+            || away_changed
+            || dry_run_changed
+            || std::mem::take(&mut self.refresh_requested)
             || self.last_session_refresh.elapsed() >= self.session_refresh_interval;
 
         if !should_refresh && !foreground_changed {
@@ -86,6 +156,32 @@ impl MuterEngine {
             });
         }
 
+        if should_refresh {
+            self.audit
+                .lock()
+                .set_file(audit_to_file.then(Config::audit_log_path));
+            self.script.sync(script_path);
+        }
+
+        // Remember what triggered this update for the audit log
+        let focus_change = if foreground_changed {
+            Some(FocusChange {
+                from_pid: previous_foreground_pid,
+                to_pid: foreground_pid,
+                to_app: foreground_pid.and_then(|pid| self.process_name_of(pid)),
+            })
+        } else {
+            None
+        };
+        if let Some(change) = &focus_change {
+            events::publish(Event::ForegroundChanged(change.clone()));
+        }
+
+        // Window state only matters under the visibility/workspace policies
+        if should_refresh {
+            policy.load_window_state();
+        }
+
         // Refresh audio sessions (expensive COM operation)
         let sessions = if should_refresh {
             self.last_session_refresh = Instant::now();
@@ -95,6 +191,63 @@ impl MuterEngine {
             Vec::new()
         };
 
+        // The policy script sees the same sessions the rules below are applied to
+        if self.script.is_loaded() {
+            let script_sessions: Vec<ScriptSession> = if should_refresh {
+                sessions
+                    .iter()
+                    .map(|session| ScriptSession {
+                        pid: session.process_id,
+                        app: session.process_name.clone(),
+                        title: session.display_name.clone(),
+                        muted: session.is_muted,
+                        muted_by_us: self.muted_pids.contains(&session.process_id),
+                        device: session.device_name.clone(),
+                    })
+                    .collect()
+            } else {
+                self.app_states
+                    .values()
+                    .filter(|state| state.is_active)
+                    .map(|state| ScriptSession {
+                        pid: state.pid,
+                        app: state.process_name.clone(),
+                        title: state.display_name.clone(),
+                        // Without a session refresh, our own mutes are all we know
+                        muted: state.is_muted_by_us,
+                        muted_by_us: state.is_muted_by_us,
+                        device: state.device_name.clone(),
+                    })
+                    .collect()
+            };
+            let context = ScriptContext {
+                foreground_pid,
+                foreground_app: foreground_pid.and_then(|pid| self.process_name_of(pid)),
+                user_away: policy.user_away,
+                peek_active: policy.peek_active,
+                unix_time: unix_now(),
+                utc_offset_secs: local_utc_offset_secs(),
+            };
+            self.script_decisions = self.script.decide_all(&context, &script_sessions);
+        } else {
+            self.script_decisions.clear();
+        }
+        policy.script_decisions = self.script_decisions.clone();
+        let record = |pid: u32, app: &str, action: AuditAction, reason: ReasonCode| {
+            self.audit.lock().record(AuditEvent {
+                dry_run,
+                ..AuditEvent::new(pid, app, action, reason, focus_change.clone())
+            });
+        };
+
+        // In dry-run, decisions are tracked and logged but audio is never touched
+        let audio = &self.audio_manager;
+        let set_muted = |pid: u32, muted: bool| {
+            if !dry_run {
+                let _ = if muted { audio.mute_process(pid) } else { audio.unmute_process(pid) };
+            }
+        };
+
         // Track which PIDs we've seen this update
         let mut seen_pids = HashSet::new();
 
@@ -102,10 +255,13 @@ impl MuterEngine {
         for session in &sessions {
             seen_pids.insert(session.process_id);
 
-            let is_foreground = foreground_pid == Some(session.process_id);
-            let is_excluded = excluded_apps.contains(&session.process_name.to_lowercase());
-            let is_always_muted = always_muted_apps.contains(&session.process_name.to_lowercase());
-            let is_own_process = session.process_id == self.own_pid;
+            let decision = decide(
+                &policy,
+                session.process_id,
+                &session.process_name,
+                foreground_pid,
+                self.own_pid,
+            );
 
             let app_state = self
                 .app_states
@@ -116,52 +272,28 @@ impl MuterEngine {
                     display_name: session.display_name.clone(),
                     is_muted_by_us: false,
                     original_mute_state: session.is_muted,
+                    device_name: session.device_name.clone(),
                     last_seen: Instant::now(),
                     is_active: true,
+                    dry_run_unmuted: false,
                 });
 
             app_state.last_seen = Instant::now();
             app_state.is_active = true;
             app_state.display_name = session.display_name.clone();
+            app_state.device_name = session.device_name.clone();
 
-            // Apply muting logic if enabled
-            if muting_enabled && !is_own_process {
-                // Priority 1: Always-muted apps stay muted even when foreground.
-                if is_always_muted {
-                    if !session.is_muted {
-                        app_state.original_mute_state = session.is_muted;
-                        let _ = self.audio_manager.mute_process(session.process_id);
-                        app_state.is_muted_by_us = true;
-                        self.muted_pids.insert(session.process_id);
-                    }
-                } else if is_excluded {
-                    // Excluded apps are always audible; if we muted them earlier, undo it.
-                    if app_state.is_muted_by_us {
-                        let _ = self.audio_manager.unmute_process(session.process_id);
-                        app_state.is_muted_by_us = false;
-                        self.muted_pids.remove(&session.process_id);
-                    }
-                } else if is_foreground {
-                    // Always try to unmute the foreground app, even if it was muted externally.
-                    let _ = self.audio_manager.unmute_process(session.process_id);
-                    app_state.is_muted_by_us = false;
-                    self.muted_pids.remove(&session.process_id);
-                } else {
-                    // Mute background app (only if currently unmuted)
-                    if !session.is_muted {
-                        app_state.original_mute_state = session.is_muted;
-                        let _ = self.audio_manager.mute_process(session.process_id);
-                        app_state.is_muted_by_us = true;
-                        self.muted_pids.insert(session.process_id);
-                    }
-                }
-            } else if !muting_enabled {
-                // Muting disabled: unmute anything we previously muted.
-                if app_state.is_muted_by_us {
-                    let _ = self.audio_manager.unmute_process(session.process_id);
-                    app_state.is_muted_by_us = false;
-                    self.muted_pids.remove(&session.process_id);
-                }
+            let change = session_change(decision.action(), session.is_muted, app_state, dry_run);
+            if let Some(muted) = change.set_muted {
+                set_muted(session.process_id, muted);
+            }
+            if app_state.is_muted_by_us {
+                self.muted_pids.insert(session.process_id);
+            } else {
+                self.muted_pids.remove(&session.process_id);
+            }
+            if let Some(action) = change.record {
+                record(session.process_id, &session.process_name, action, decision.into());
             }
         }
 
@@ -172,41 +304,43 @@ impl MuterEngine {
                     continue;
                 }
 
-                let is_foreground = foreground_pid == Some(*pid);
-                let is_excluded = excluded_apps.contains(&state.process_name.to_lowercase());
-                let is_always_muted = always_muted_apps.contains(&state.process_name.to_lowercase());
+                let decision =
+                    decide(&policy, *pid, &state.process_name, foreground_pid, self.own_pid);
+                let reason = ReasonCode::from(decision);
 
-                if muting_enabled && *pid != self.own_pid {
-                    if is_always_muted {
-                        let _ = self.audio_manager.mute_process(*pid);
-                        state.is_muted_by_us = true;
-                        self.muted_pids.insert(*pid);
-                    } else if is_excluded {
-                        if state.is_muted_by_us {
-                            let _ = self.audio_manager.unmute_process(*pid);
-                            state.is_muted_by_us = false;
-                            self.muted_pids.remove(pid);
-                        }
-                    } else if is_foreground {
-                        let _ = self.audio_manager.unmute_process(*pid);
-                        state.is_muted_by_us = false;
-                        self.muted_pids.remove(pid);
-                    } else {
+                match decision.action() {
+                    MuteAction::Mute => {
                         // Avoid muting every background PID here (we don't have fresh mute state).
                         // Only newly-mute the PID that just lost focus, plus anything we already muted.
-                        let should_mute = state.is_muted_by_us || previous_foreground_pid == Some(*pid);
+                        let should_mute = decision != Decision::Background
+                            || state.is_muted_by_us
+                            || previous_foreground_pid == Some(*pid);
                         if should_mute {
-                            let _ = self.audio_manager.mute_process(*pid);
+                            set_muted(*pid, true);
+                            if !state.is_muted_by_us {
+                                record(*pid, &state.process_name, AuditAction::Muted, reason);
+                            }
                             state.is_muted_by_us = true;
                             self.muted_pids.insert(*pid);
                         }
                     }
-                } else if !muting_enabled {
-                    if state.is_muted_by_us {
-                        let _ = self.audio_manager.unmute_process(*pid);
+                    MuteAction::Unmute => {
+                        set_muted(*pid, false);
+                        if state.is_muted_by_us {
+                            record(*pid, &state.process_name, AuditAction::Unmuted, reason);
+                        }
                         state.is_muted_by_us = false;
                         self.muted_pids.remove(pid);
                     }
+                    MuteAction::Release => {
+                        if state.is_muted_by_us {
+                            set_muted(*pid, false);
+                            state.is_muted_by_us = false;
+                            self.muted_pids.remove(pid);
+                            record(*pid, &state.process_name, AuditAction::Unmuted, reason);
+                        }
+                    }
+                    MuteAction::Ignore => {}
                 }
             }
         }
@@ -222,12 +356,18 @@ impl MuterEngine {
 
                     // Unmute if we were muting this app
                     if state.is_muted_by_us {
-                        let _ = self.audio_manager.unmute_process(*pid);
+                        set_muted(*pid, false);
+                        state.is_muted_by_us = false;
                         self.muted_pids.remove(pid);
+                        let reason = ReasonCode::SessionGone;
+                        record(*pid, &state.process_name, AuditAction::Unmuted, reason);
                     }
 
                     // Remove if not seen for too long
                     if now.duration_since(state.last_seen) > cleanup_threshold {
+                        if self.pinned_pid == Some(*pid) {
+                            self.pinned_pid = None;
+                        }
                         return false;
                     }
                 }
@@ -243,6 +383,132 @@ impl MuterEngine {
         })
     }
 
+    /// Builds the decision inputs from the config and the engine's own state
+    fn policy_inputs(&self) -> PolicyInputs {
+        let mut policy = PolicyInputs::from_config(&self.config.read());
+        policy.pinned_pid = self.pinned_pid;
+        policy.peek_active = self.peek_active;
+        policy.user_away = self.user_away;
+        policy.script_decisions = self.script_decisions.clone();
+        policy
+    }
+
+    /// Explains why an app is (or would be) muted or audible right now
+    ///
+    /// Runs the same `decide` as `update` against the current inputs,
+    /// so the answer always matches what the engine does.
+    pub fn explain(&self, pid: u32) -> Explanation {
+        let mut policy = self.policy_inputs();
+        policy.load_window_state();
+
+        let state = self.app_states.get(&pid);
+        let process_name = state
+            .map(|s| s.process_name.clone())
+            .or_else(|| get_process_name(pid));
+        let name = process_name.as_deref().unwrap_or_default();
+        let decision = decide(&policy, pid, name, self.last_foreground_pid, self.own_pid);
+
+        Explanation {
+            pid,
+            process_name: process_name.clone(),
+            display_name: state.map(|s| s.display_name.clone()),
+            has_audio_session: state.is_some_and(|s| s.is_active),
+            muted_by_us: self.muted_pids.contains(&pid),
+            matched_rules: matched_rules(&policy, pid, name),
+            muting_enabled: policy.muting_enabled,
+            snoozed: policy.snoozed,
+            user_away: policy.user_away,
+            peek_active: policy.peek_active,
+            dry_run: self.dry_run,
+            audibility: policy.audibility,
+            foreground_pid: self.last_foreground_pid,
+            foreground_app: self.foreground_app().map(|(_, name)| name),
+            previous_foreground_pid: self.previous_foreground_pid,
+            decision,
+            action: decision.action(),
+        }
+    }
+
+    /// Picks up the current foreground app without touching any audio sessions
+    pub fn refresh_foreground(&mut self) {
+        let policy = self.policy_inputs();
+        self.last_foreground_pid = self.resolve_foreground(&policy);
+    }
+
+    /// Resolves the effective foreground PID
+    /// Focusing a focus-transparent window (taskbar, desktop, launcher, our own UI)
+    /// leaves the previously focused app in the foreground.
+    fn resolve_foreground(&mut self, policy: &PolicyInputs) -> Option<u32> {
+        let window = get_foreground_window()?;
+        let process_name = self.process_name_of(window.pid);
+
+        if is_focus_transparent(policy, &window, process_name.as_deref(), self.own_pid) {
+            self.last_foreground_pid
+        } else {
+            Some(window.pid)
+        }
+    }
+
+    /// Looks up a process name, preferring known sessions over opening the process
+    fn process_name_of(&mut self, pid: u32) -> Option<String> {
+        if let Some(state) = self.app_states.get(&pid) {
+            return Some(state.process_name.clone());
+        }
+
+        match &self.foreground_name_cache {
+            Some((cached_pid, name)) if *cached_pid == pid => name.clone(),
+            _ => {
+                let name = get_process_name(pid);
+                self.foreground_name_cache = Some((pid, name.clone()));
+                name
+            }
+        }
+    }
+
+    /// Gets the effective foreground app (pid, process name), if known
+    pub fn foreground_app(&self) -> Option<(u32, String)> {
+        let pid = self.last_foreground_pid?;
+        let name = match self.app_states.get(&pid) {
+            Some(state) => state.process_name.clone(),
+            None => match &self.foreground_name_cache {
+                Some((cached_pid, Some(name))) if *cached_pid == pid => name.clone(),
+                _ => get_process_name(pid)?,
+            },
+        };
+        Some((pid, name))
+    }
+
+    /// Pins the foreground app audible, or unpins it if it is already pinned
+    /// Returns the newly pinned app, or None if the pin was released.
+    pub fn toggle_pin_foreground(&mut self) -> Option<(u32, String)> {
+        let app = self.foreground_app();
+        match (&app, self.pinned_pid) {
+            (Some((pid, _)), Some(pinned)) if *pid == pinned => self.pinned_pid = None,
+            (Some((pid, _)), _) => self.pinned_pid = Some(*pid),
+            (None, _) => self.pinned_pid = None,
+        }
+        self.refresh_requested = true;
+        app.filter(|(pid, _)| self.pinned_pid == Some(*pid))
+    }
+
+    /// Gets the pinned PID, if any
+    pub fn pinned_pid(&self) -> Option<u32> {
+        self.pinned_pid
+    }
+
+    /// Starts or stops peeking (all background apps audible)
+    pub fn set_peek(&mut self, active: bool) {
+        if self.peek_active != active {
+            self.peek_active = active;
+            self.refresh_requested = true;
+        }
+    }
+
+    /// Makes the next update refresh sessions and re-apply every decision
+    pub fn request_refresh(&mut self) {
+        self.refresh_requested = true;
+    }
+
     /// Gets the current app states
     pub fn get_app_states(&self) -> Vec<AppAudioState> {
         self.app_states.values().cloned().collect()
@@ -260,14 +526,39 @@ impl MuterEngine {
     /// Unmutes all apps that we muted
     pub fn unmute_all(&mut self) {
         for pid in self.muted_pids.drain() {
-            let _ = self.audio_manager.unmute_process(pid);
+            if !self.dry_run {
+                let _ = self.audio_manager.unmute_process(pid);
+            }
+            let app = self
+                .app_states
+                .get(&pid)
+                .map(|state| state.process_name.as_str())
+                .unwrap_or_default();
+            self.audit.lock().record(AuditEvent {
+                dry_run: self.dry_run,
+                ..AuditEvent::new(pid, app, AuditAction::Unmuted, ReasonCode::Disabled, None)
+            });
+        }
+
+        for state in self.app_states.values_mut() {
+            state.is_muted_by_us = false;
         }
+    }
 
+    /// Drops simulated dry-run mutes without touching audio
+    fn forget_mutes(&mut self) {
+        self.muted_pids.clear();
         for state in self.app_states.values_mut() {
             state.is_muted_by_us = false;
+            state.dry_run_unmuted = false;
         }
     }
 
+    /// Whether decisions are currently simulated only
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
     /// Forces a refresh of audio sessions
     pub fn force_refresh(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.audio_manager.refresh_sessions()?;
@@ -280,6 +571,11 @@ impl MuterEngine {
         self.muted_pids.len()
     }
 
+    /// Whether the engine currently treats the user as away
+    pub fn is_user_away(&self) -> bool {
+        self.user_away
+    }
+
     /// Checks if a specific PID is muted by us
     pub fn is_muted_by_us(&self, pid: u32) -> bool {
         self.muted_pids.contains(&pid)
@@ -302,3 +598,566 @@ pub struct UpdateResult {
     pub active_sessions: usize,
     pub muted_count: usize,
 }
+
+/// Snapshot of the config-derived inputs for one update cycle
+#[derive(Debug, Clone, Default)]
+struct PolicyInputs {
+    muting_enabled: bool,
+    snoozed: bool,
+    excluded_apps: HashSet<String>,
+    exempt_apps: HashSet<String>,
+    always_muted_apps: HashSet<String>,
+    away_allowed_apps: HashSet<String>,
+    focus_transparent_apps: HashSet<String>,
+    focus_transparent_classes: HashSet<String>,
+    audibility: AudibilityPolicy,
+    visible_pids: HashSet<u32>,
+    workspace_pids: HashSet<u32>,
+    pinned_pid: Option<u32>,
+    peek_active: bool,
+    user_away: bool,
+    /// What the policy script decided, for the sessions it didn't leave alone
+    script_decisions: HashMap<u32, Decision>,
+}
+
+impl PolicyInputs {
+    /// Fills in window state for the visibility/workspace policies
+    fn load_window_state(&mut self) {
+        match self.audibility {
+            AudibilityPolicy::Focus => {}
+            AudibilityPolicy::Visible => self.visible_pids = get_visible_window_pids(),
+            AudibilityPolicy::Workspace => self.workspace_pids = get_current_desktop_window_pids(),
+        }
+    }
+
+    fn from_config(config: &Config) -> Self {
+        Self {
+            muting_enabled: config.muting_enabled,
+            snoozed: config.is_snoozed(),
+            excluded_apps: config.excluded_apps.clone(),
+            exempt_apps: config.active_exemptions(),
+            always_muted_apps: config.always_muted_apps.clone(),
+            away_allowed_apps: config.away_allowed_apps.clone(),
+            focus_transparent_apps: config.focus_transparent_apps.clone(),
+            focus_transparent_classes: config.focus_transparent_classes.clone(),
+            audibility: config.audibility_policy,
+            visible_pids: HashSet::new(),
+            workspace_pids: HashSet::new(),
+            pinned_pid: None,
+            peek_active: false,
+            user_away: false,
+            script_decisions: HashMap::new(),
+        }
+    }
+}
+
+/// Why a session ends up muted or audible
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Our own process is never touched
+    OwnProcess,
+    /// Muting is turned off
+    Disabled,
+    /// Muting is snoozed for a while
+    Snoozed,
+    /// The user is away and the app is not on the away allowlist
+    Away,
+    /// The user is away but the app is on the away allowlist
+    AwayAllowed,
+    /// The app is on the always-muted list
+    AlwaysMuted,
+    /// The app is on the exclusion list
+    Excluded,
+    /// The app has an unexpired temporary exemption
+    Exempt,
+    /// The app was pinned audible with a hotkey
+    Pinned,
+    /// The app owns the foreground window
+    Foreground,
+    /// The app has a visible window (visibility policy)
+    Visible,
+    /// The app has a window on the current virtual desktop (workspace policy)
+    CurrentWorkspace,
+    /// The peek hotkey is held
+    Peek,
+    /// The app is in the background
+    Background,
+    /// The policy script asked for the session to be muted
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    ScriptMute,
+    /// The policy script asked for the session to be audible, even if muted elsewhere
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    ScriptUnmute,
+    /// The policy script asked for our mute to be lifted
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    ScriptAllow,
+}
+
+/// What to do with a session's mute state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MuteAction {
+    /// Make sure the session is muted
+    Mute,
+    /// Make sure the session is audible, even if something else muted it
+    Unmute,
+    /// Undo our own mute, leave anything else alone
+    Release,
+    /// Never touch the session
+    Ignore,
+}
+
+impl Decision {
+    /// Gets the action this decision translates to
+    pub fn action(self) -> MuteAction {
+        match self {
+            Decision::OwnProcess => MuteAction::Ignore,
+            Decision::Disabled
+            | Decision::Snoozed
+            | Decision::AwayAllowed
+            | Decision::Excluded
+            | Decision::Exempt
+            | Decision::Pinned
+            | Decision::Visible
+            | Decision::CurrentWorkspace
+            | Decision::Peek
+            | Decision::ScriptAllow => MuteAction::Release,
+            Decision::Away
+            | Decision::AlwaysMuted
+            | Decision::Background
+            | Decision::ScriptMute => MuteAction::Mute,
+            Decision::Foreground | Decision::ScriptUnmute => MuteAction::Unmute,
+        }
+    }
+}
+
+/// The inputs and outcome of the decision for one app
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub pid: u32,
+    pub process_name: Option<String>,
+    pub display_name: Option<String>,
+    pub has_audio_session: bool,
+    pub muted_by_us: bool,
+    /// Every rule that mentions the app, whether or not it won
+    pub matched_rules: Vec<String>,
+    pub muting_enabled: bool,
+    pub snoozed: bool,
+    pub user_away: bool,
+    pub peek_active: bool,
+    pub dry_run: bool,
+    pub audibility: AudibilityPolicy,
+    pub foreground_pid: Option<u32>,
+    pub foreground_app: Option<String>,
+    /// The app that had the foreground before the current one
+    pub previous_foreground_pid: Option<u32>,
+    pub decision: Decision,
+    pub action: MuteAction,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pid = |pid: Option<u32>| pid.map(|p| p.to_string()).unwrap_or_else(|| "none".into());
+
+        let name = self.process_name.as_deref().unwrap_or("<unknown process>");
+        writeln!(f, "{} (pid {})", name, self.pid)?;
+        if let Some(display_name) = self.display_name.as_deref().filter(|d| !d.is_empty()) {
+            writeln!(f, "Session: {}", display_name)?;
+        }
+        writeln!(
+            f,
+            "Audio session: {}, muted by us: {}",
+            if self.has_audio_session { "active" } else { "none" },
+            if self.muted_by_us { "yes" } else { "no" }
+        )?;
+        writeln!(f)?;
+
+        writeln!(f, "Decision: {:?} -> {:?}", self.decision, self.action)?;
+        if self.matched_rules.is_empty() {
+            writeln!(f, "Matching rules: none")?;
+        } else {
+            writeln!(f, "Matching rules: {}", self.matched_rules.join(", "))?;
+        }
+        writeln!(f)?;
+
+        write!(f, "Foreground: {}", pid(self.foreground_pid))?;
+        if let Some(app) = &self.foreground_app {
+            write!(f, " ({})", app)?;
+        }
+        writeln!(f, ", previous: {}", pid(self.previous_foreground_pid))?;
+        write!(
+            f,
+            "Muting enabled: {}, snoozed: {}, away: {}, peek: {}, audibility: {:?}",
+            self.muting_enabled, self.snoozed, self.user_away, self.peek_active, self.audibility
+        )?;
+        if self.dry_run {
+            write!(f, "\nDry run: decisions are logged but audio is not touched")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the rules that mention an app, in decision priority order
+/// What a session refresh does to one session
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SessionChange {
+    /// The mute state to set on the session
+    set_muted: Option<bool>,
+    /// The action to put in the audit log
+    record: Option<AuditAction>,
+}
+
+/// Applies `action` to a refreshed session's state
+///
+/// In dry-run nothing is set for real, so a simulated mute is tracked by
+/// `is_muted_by_us` and a simulated unmute by `dry_run_unmuted`.
+fn session_change(
+    action: MuteAction,
+    session_muted: bool,
+    state: &mut AppAudioState,
+    dry_run: bool,
+) -> SessionChange {
+    let unmuted_before = std::mem::take(&mut state.dry_run_unmuted);
+    match action {
+        MuteAction::Mute => {
+            // Only mute if currently unmuted, so we never claim a user's own mute
+            // (a simulated mute leaves the session unmuted, so it is tracked instead)
+            let simulated = dry_run && state.is_muted_by_us;
+            if session_muted || simulated {
+                return SessionChange::default();
+            }
+            state.original_mute_state = session_muted;
+            state.is_muted_by_us = true;
+            SessionChange {
+                set_muted: Some(true),
+                record: Some(AuditAction::Muted),
+            }
+        }
+        MuteAction::Unmute => {
+            // Always unmute, even if it was muted externally
+            let was_muted = session_muted || state.is_muted_by_us;
+            let repeated = dry_run && !state.is_muted_by_us && unmuted_before;
+            state.is_muted_by_us = false;
+            state.dry_run_unmuted = dry_run && session_muted;
+            SessionChange {
+                set_muted: Some(false),
+                record: (was_muted && !repeated).then_some(AuditAction::Unmuted),
+            }
+        }
+        MuteAction::Release if state.is_muted_by_us => {
+            // Undo our own mute, leave anything else alone
+            state.is_muted_by_us = false;
+            SessionChange {
+                set_muted: Some(false),
+                record: Some(AuditAction::Unmuted),
+            }
+        }
+        MuteAction::Release | MuteAction::Ignore => SessionChange::default(),
+    }
+}
+
+fn matched_rules(policy: &PolicyInputs, pid: u32, process_name: &str) -> Vec<String> {
+    let name = process_name.to_lowercase();
+    let mut rules = Vec::new();
+
+    if policy.script_decisions.contains_key(&pid) {
+        rules.push("policy_script".to_string());
+    }
+    if policy.away_allowed_apps.contains(&name) {
+        rules.push("away_allowed_apps".to_string());
+    }
+    if policy.always_muted_apps.contains(&name) {
+        rules.push("always_muted_apps".to_string());
+    }
+    if policy.excluded_apps.contains(&name) {
+        rules.push("excluded_apps".to_string());
+    }
+    if policy.exempt_apps.contains(&name) {
+        rules.push("temporary_exemptions".to_string());
+    }
+    if policy.pinned_pid == Some(pid) {
+        rules.push("pinned".to_string());
+    }
+    if policy.visible_pids.contains(&pid) {
+        rules.push("visible window".to_string());
+    }
+    if policy.workspace_pids.contains(&pid) {
+        rules.push("window on current desktop".to_string());
+    }
+    rules
+}
+
+/// Checks if focusing this window should leave the previous app audible
+fn is_focus_transparent(
+    policy: &PolicyInputs,
+    window: &ForegroundWindow,
+    process_name: Option<&str>,
+    own_pid: u32,
+) -> bool {
+    if window.pid == own_pid {
+        // Our own settings window and tray menu
+        return true;
+    }
+
+    if policy
+        .focus_transparent_classes
+        .contains(&window.class_name.to_lowercase())
+    {
+        return true;
+    }
+
+    process_name
+        .map(|name| policy.focus_transparent_apps.contains(&name.to_lowercase()))
+        .unwrap_or(false)
+}
+
+/// Decides the fate of a single session - the one place the priority order lives
+fn decide(
+    policy: &PolicyInputs,
+    pid: u32,
+    process_name: &str,
+    foreground_pid: Option<u32>,
+    own_pid: u32,
+) -> Decision {
+    let name = process_name.to_lowercase();
+
+    if pid == own_pid {
+        Decision::OwnProcess
+    } else if !policy.muting_enabled {
+        Decision::Disabled
+    } else if policy.snoozed {
+        Decision::Snoozed
+    } else if let Some(&decision) = policy.script_decisions.get(&pid) {
+        // The script goes first; sessions it leaves alone get the rules below
+        decision
+    } else if policy.user_away {
+        // While away everything goes quiet except the allowlist
+        if policy.away_allowed_apps.contains(&name) {
+            Decision::AwayAllowed
+        } else {
+            Decision::Away
+        }
+    } else if policy.always_muted_apps.contains(&name) {
+        // Always-muted apps stay muted even when foreground
+        Decision::AlwaysMuted
+    } else if policy.excluded_apps.contains(&name) {
+        Decision::Excluded
+    } else if policy.exempt_apps.contains(&name) {
+        Decision::Exempt
+    } else if policy.pinned_pid == Some(pid) {
+        Decision::Pinned
+    } else if foreground_pid == Some(pid) {
+        Decision::Foreground
+    } else if policy.audibility == AudibilityPolicy::Visible && policy.visible_pids.contains(&pid) {
+        Decision::Visible
+    } else if policy.audibility == AudibilityPolicy::Workspace
+        && policy.workspace_pids.contains(&pid)
+    {
+        Decision::CurrentWorkspace
+    } else if policy.peek_active {
+        Decision::Peek
+    } else {
+        Decision::Background
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PolicyInputs {
+        PolicyInputs {
+            muting_enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_foreground_and_background() {
+        let policy = policy();
+        assert_eq!(decide(&policy, 10, "game.exe", Some(10), 1), Decision::Foreground);
+        assert_eq!(decide(&policy, 11, "chrome.exe", Some(10), 1), Decision::Background);
+        assert_eq!(decide(&policy, 1, "bg-muter.exe", Some(10), 1), Decision::OwnProcess);
+    }
+
+    #[test]
+    fn test_list_priority() {
+        let mut policy = policy();
+        policy.excluded_apps.insert("spotify.exe".to_string());
+        policy.always_muted_apps.insert("ads.exe".to_string());
+
+        assert_eq!(decide(&policy, 11, "Spotify.exe", Some(10), 1), Decision::Excluded);
+        assert_eq!(decide(&policy, 12, "ads.exe", Some(12), 1), Decision::AlwaysMuted);
+
+        policy.muting_enabled = false;
+        assert_eq!(decide(&policy, 12, "ads.exe", Some(12), 1), Decision::Disabled);
+    }
+
+    #[test]
+    fn test_visibility_policy() {
+        let mut policy = policy();
+        policy.visible_pids.insert(11);
+        assert_eq!(decide(&policy, 11, "stream.exe", Some(10), 1), Decision::Background);
+
+        policy.audibility = AudibilityPolicy::Visible;
+        assert_eq!(decide(&policy, 11, "stream.exe", Some(10), 1), Decision::Visible);
+        assert_eq!(decide(&policy, 12, "hidden.exe", Some(10), 1), Decision::Background);
+        assert_eq!(Decision::Visible.action(), MuteAction::Release);
+    }
+
+    #[test]
+    fn test_workspace_policy() {
+        let mut policy = policy();
+        policy.audibility = AudibilityPolicy::Workspace;
+        policy.workspace_pids.insert(11);
+
+        assert_eq!(decide(&policy, 11, "music.exe", Some(10), 1), Decision::CurrentWorkspace);
+        assert_eq!(decide(&policy, 12, "other-desk.exe", Some(10), 1), Decision::Background);
+        assert_eq!(decide(&policy, 10, "game.exe", Some(10), 1), Decision::Foreground);
+    }
+
+    #[test]
+    fn test_temporary_exemption() {
+        let mut policy = policy();
+        policy.exempt_apps.insert("spotify.exe".to_string());
+        assert_eq!(decide(&policy, 11, "Spotify.exe", Some(10), 1), Decision::Exempt);
+        assert_eq!(Decision::Exempt.action(), MuteAction::Release);
+    }
+
+    #[test]
+    fn test_pin_and_peek() {
+        let mut policy = policy();
+        policy.always_muted_apps.insert("ads.exe".to_string());
+        policy.pinned_pid = Some(11);
+        assert_eq!(decide(&policy, 11, "music.exe", Some(10), 1), Decision::Pinned);
+        assert_eq!(decide(&policy, 12, "chrome.exe", Some(10), 1), Decision::Background);
+
+        policy.peek_active = true;
+        assert_eq!(decide(&policy, 12, "chrome.exe", Some(10), 1), Decision::Peek);
+        assert_eq!(decide(&policy, 13, "ads.exe", Some(10), 1), Decision::AlwaysMuted);
+    }
+
+    #[test]
+    fn test_matched_rules() {
+        let mut policy = policy();
+        policy.excluded_apps.insert("spotify.exe".to_string());
+        policy.always_muted_apps.insert("spotify.exe".to_string());
+        policy.pinned_pid = Some(11);
+
+        assert_eq!(
+            matched_rules(&policy, 11, "Spotify.exe"),
+            vec!["always_muted_apps", "excluded_apps", "pinned"]
+        );
+        assert!(matched_rules(&policy, 12, "chrome.exe").is_empty());
+    }
+
+    #[test]
+    fn test_focus_transparency() {
+        let mut policy = policy();
+        policy.focus_transparent_apps.insert("searchhost.exe".to_string());
+        policy.focus_transparent_classes.insert("shell_traywnd".to_string());
+
+        let window = |pid: u32, class_name: &str| ForegroundWindow {
+            pid,
+            class_name: class_name.to_string(),
+        };
+
+        assert!(is_focus_transparent(&policy, &window(1, "BgMuterSettingsClass"), None, 1));
+        assert!(is_focus_transparent(&policy, &window(5, "Shell_TrayWnd"), Some("explorer.exe"), 1));
+        assert!(is_focus_transparent(&policy, &window(6, "Windows.UI.Core"), Some("SearchHost.exe"), 1));
+        assert!(!is_focus_transparent(&policy, &window(7, "CabinetWClass"), Some("explorer.exe"), 1));
+        assert!(!is_focus_transparent(&policy, &window(8, "Chrome_WidgetWin_1"), None, 1));
+    }
+
+    #[test]
+    fn test_snoozed() {
+        let mut policy = policy();
+        policy.snoozed = true;
+        policy.always_muted_apps.insert("ads.exe".to_string());
+
+        assert_eq!(decide(&policy, 11, "chrome.exe", Some(10), 1), Decision::Snoozed);
+        assert_eq!(decide(&policy, 12, "ads.exe", Some(10), 1), Decision::Snoozed);
+        assert_eq!(Decision::Snoozed.action(), MuteAction::Release);
+    }
+
+    #[test]
+    fn test_user_away() {
+        let mut policy = policy();
+        policy.user_away = true;
+        policy.excluded_apps.insert("spotify.exe".to_string());
+        policy.away_allowed_apps.insert("discord.exe".to_string());
+
+        assert_eq!(decide(&policy, 10, "game.exe", Some(10), 1), Decision::Away);
+        assert_eq!(decide(&policy, 11, "spotify.exe", Some(10), 1), Decision::Away);
+        assert_eq!(decide(&policy, 12, "discord.exe", Some(10), 1), Decision::AwayAllowed);
+        assert_eq!(Decision::Away.action(), MuteAction::Mute);
+        assert_eq!(Decision::AwayAllowed.action(), MuteAction::Release);
+    }
+
+    #[test]
+    fn test_script_decisions() {
+        let mut policy = policy();
+        policy.user_away = true;
+        policy.excluded_apps.insert("spotify.exe".to_string());
+        policy.script_decisions.insert(11, Decision::ScriptAllow);
+        policy.script_decisions.insert(12, Decision::ScriptMute);
+
+        // The script goes before the rules, but not before the on/off switches
+        assert_eq!(decide(&policy, 11, "chrome.exe", Some(10), 1), Decision::ScriptAllow);
+        assert_eq!(decide(&policy, 12, "spotify.exe", Some(10), 1), Decision::ScriptMute);
+        assert_eq!(decide(&policy, 13, "spotify.exe", Some(10), 1), Decision::Away);
+        assert_eq!(
+            matched_rules(&policy, 12, "spotify.exe"),
+            vec!["policy_script", "excluded_apps"]
+        );
+
+        policy.snoozed = true;
+        assert_eq!(decide(&policy, 12, "spotify.exe", Some(10), 1), Decision::Snoozed);
+        assert_eq!(Decision::ScriptUnmute.action(), MuteAction::Unmute);
+    }
+
+    fn app_state() -> AppAudioState {
+        AppAudioState {
+            pid: 7,
+            process_name: "player.exe".into(),
+            display_name: String::new(),
+            is_muted_by_us: false,
+            original_mute_state: false,
+            device_name: String::new(),
+            last_seen: Instant::now(),
+            is_active: true,
+            dry_run_unmuted: false,
+        }
+    }
+
+    #[test]
+    fn test_dry_run_unmute_is_recorded_once() {
+        // The user muted the app themselves; dry-run never lifts that mute
+        let mut state = app_state();
+        let records: Vec<_> = (0..2)
+            .filter_map(|_| session_change(MuteAction::Unmute, true, &mut state, true).record)
+            .collect();
+        assert_eq!(records, vec![AuditAction::Unmuted]);
+
+        // Losing focus and coming back is a new unmute
+        session_change(MuteAction::Release, true, &mut state, true);
+        let again = session_change(MuteAction::Unmute, true, &mut state, true);
+        assert_eq!(again.record, Some(AuditAction::Unmuted));
+    }
+
+    #[test]
+    fn test_dry_run_mute_is_recorded_once() {
+        let mut state = app_state();
+        let first = session_change(MuteAction::Mute, false, &mut state, true);
+        assert_eq!(first.record, Some(AuditAction::Muted));
+        assert!(state.is_muted_by_us);
+        // The session is still audible on the next refresh
+        let again = session_change(MuteAction::Mute, false, &mut state, true);
+        assert_eq!(again, SessionChange::default());
+
+        let unmute = session_change(MuteAction::Unmute, false, &mut state, true);
+        assert_eq!(unmute.record, Some(AuditAction::Unmuted));
+        assert!(!state.is_muted_by_us);
+    }
+}
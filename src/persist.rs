@@ -0,0 +1,178 @@
+//! Config persistence module
+//! Writes files atomically (temp file + rename, previous generation kept as
+//! `.bak`) on a background thread that coalesces bursts of saves.
+
+use crossbeam_channel::{unbounded, RecvTimeoutError, Sender};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for more changes before writing
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+static PERSISTER: OnceLock<Persister> = OnceLock::new();
+
+enum Request {
+    Save(PathBuf, String),
+    Flush(Sender<()>),
+}
+
+/// Handle to a background writer thread
+pub struct Persister {
+    tx: Sender<Request>,
+}
+
+impl Persister {
+    /// Starts a writer that waits `debounce` after the last save before writing
+    pub fn spawn(debounce: Duration) -> Self {
+        let (tx, rx) = unbounded();
+
+        thread::spawn(move || {
+            let mut pending: Option<(PathBuf, String)> = None;
+
+            loop {
+                let request = if pending.is_some() {
+                    match rx.recv_timeout(debounce) {
+                        Ok(request) => request,
+                        Err(RecvTimeoutError::Timeout) => {
+                            write_pending(&mut pending);
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                } else {
+                    match rx.recv() {
+                        Ok(request) => request,
+                        Err(_) => break,
+                    }
+                };
+
+                match request {
+                    // Only the newest contents matter
+                    Request::Save(path, contents) => pending = Some((path, contents)),
+                    Request::Flush(done) => {
+                        write_pending(&mut pending);
+                        let _ = done.send(());
+                    }
+                }
+            }
+
+            write_pending(&mut pending);
+        });
+
+        Self { tx }
+    }
+
+    /// Queues `contents` to be written to `path`
+    pub fn save(&self, path: PathBuf, contents: String) {
+        let _ = self.tx.send(Request::Save(path, contents));
+    }
+
+    /// Writes any queued save now and waits for it to finish
+    pub fn flush(&self) {
+        let (done_tx, done_rx) = unbounded();
+        if self.tx.send(Request::Flush(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+    }
+}
+
+fn write_pending(pending: &mut Option<(PathBuf, String)>) {
+    if let Some((path, contents)) = pending.take() {
+        match write_atomic(&path, &contents) {
+            Ok(()) => log::info!("Config saved to {:?}", path),
+            Err(e) => log::error!("Failed to save {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Queues a debounced, atomic write on the shared writer thread
+pub fn save_in_background(path: PathBuf, contents: String) {
+    PERSISTER
+        .get_or_init(|| Persister::spawn(DEBOUNCE))
+        .save(path, contents);
+}
+
+/// Writes any queued save before the process exits
+pub fn flush() {
+    if let Some(persister) = PERSISTER.get() {
+        persister.flush();
+    }
+}
+
+/// Gets `<path>.<suffix>`, e.g. config.json.bak
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Replaces `path` with `contents` so readers see either the old or the new
+/// file, never a truncated one; the old file is kept as `<path>.bak`
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = sibling_path(path, "tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        if let Err(e) = fs::copy(path, sibling_path(path, "bak")) {
+            log::warn!("Failed to back up {:?}: {}", path, e);
+        }
+    }
+
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bg-muter-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("config.json");
+
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::read_to_string(sibling_path(&path, "bak")).unwrap(), "first");
+        assert!(!sibling_path(&path, "tmp").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_saves_are_coalesced() {
+        let dir = temp_dir("debounce");
+        let path = dir.join("config.json");
+        let persister = Persister::spawn(Duration::from_secs(60));
+
+        for i in 0..3 {
+            persister.save(path.clone(), format!("v{}", i));
+        }
+        persister.flush();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v2");
+        // A single write means there was no previous generation to back up
+        assert!(!sibling_path(&path, "bak").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
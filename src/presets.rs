@@ -0,0 +1,372 @@
+//! Shareable presets module
+//! Exports the app lists to a standalone file and imports them back,
+//! merging with or replacing the current lists.
+
+use crate::config::Config;
+use crate::layers;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The preset file layout this build writes
+const PRESET_VERSION: u32 = 1;
+
+/// The app lists (and window class list) a preset carries
+const LISTS: [&str; 5] = [
+    "excluded_apps",
+    "always_muted_apps",
+    "away_allowed_apps",
+    "focus_transparent_apps",
+    "focus_transparent_classes",
+];
+
+/// A shareable set of app lists
+///
+/// Unknown fields are ignored, so a whole config.json imports as a preset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    #[serde(default)]
+    pub preset_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub excluded_apps: BTreeSet<String>,
+    #[serde(default)]
+    pub always_muted_apps: BTreeSet<String>,
+    #[serde(default)]
+    pub away_allowed_apps: BTreeSet<String>,
+    #[serde(default)]
+    pub focus_transparent_apps: BTreeSet<String>,
+    #[serde(default)]
+    pub focus_transparent_classes: BTreeSet<String>,
+}
+
+/// How an imported preset combines with the current lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Add the preset's entries to the current lists
+    Merge,
+    /// Make each list the preset has entries for exactly the preset's
+    /// (lists it leaves empty and policy entries stay)
+    Replace,
+}
+
+/// An app that ended up both excluded and always muted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub app: String,
+    /// The list the app stays on
+    pub kept_in: &'static str,
+    /// The list it was taken off (None if the policy requires both)
+    pub removed_from: Option<&'static str>,
+}
+
+/// What an import changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub name: Option<String>,
+    pub mode: ImportMode,
+    pub added: Vec<(&'static str, String)>,
+    pub removed: Vec<(&'static str, String)>,
+    pub conflicts: Vec<Conflict>,
+    /// Lists left alone because a policy or override locks them
+    pub locked: Vec<&'static str>,
+}
+
+impl Preset {
+    /// Captures the current lists
+    pub fn from_config(config: &Config, name: Option<String>) -> Self {
+        let sorted = |apps: &HashSet<String>| apps.iter().cloned().collect();
+        Self {
+            preset_version: PRESET_VERSION,
+            name,
+            excluded_apps: sorted(&config.excluded_apps),
+            always_muted_apps: sorted(&config.always_muted_apps),
+            away_allowed_apps: sorted(&config.away_allowed_apps),
+            focus_transparent_apps: sorted(&config.focus_transparent_apps),
+            focus_transparent_classes: sorted(&config.focus_transparent_classes),
+        }
+    }
+
+    /// Reads a preset file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    /// Parses preset file contents
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut preset: Self = serde_json::from_str(contents)?;
+        if preset.preset_version > PRESET_VERSION {
+            return Err(format!(
+                "preset_version {} is newer than this version supports ({})",
+                preset.preset_version, PRESET_VERSION
+            )
+            .into());
+        }
+
+        for key in LISTS {
+            let list = preset.list_mut(key);
+            *list = list.iter().map(|app| app.trim().to_lowercase()).collect();
+            list.remove("");
+        }
+        Ok(preset)
+    }
+
+    /// Writes the preset as pretty JSON
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Number of entries across all lists
+    pub fn len(&self) -> usize {
+        LISTS.iter().map(|key| self.list(key).len()).sum()
+    }
+
+    /// Whether the preset has no entries (nothing to import)
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn list(&self, key: &str) -> &BTreeSet<String> {
+        match key {
+            "excluded_apps" => &self.excluded_apps,
+            "always_muted_apps" => &self.always_muted_apps,
+            "away_allowed_apps" => &self.away_allowed_apps,
+            "focus_transparent_apps" => &self.focus_transparent_apps,
+            _ => &self.focus_transparent_classes,
+        }
+    }
+
+    fn list_mut(&mut self, key: &str) -> &mut BTreeSet<String> {
+        match key {
+            "excluded_apps" => &mut self.excluded_apps,
+            "always_muted_apps" => &mut self.always_muted_apps,
+            "away_allowed_apps" => &mut self.away_allowed_apps,
+            "focus_transparent_apps" => &mut self.focus_transparent_apps,
+            _ => &mut self.focus_transparent_classes,
+        }
+    }
+
+    /// Applies the preset to `config` (without saving) and reports the changes
+    ///
+    /// An app that ends up both excluded and always muted is a conflict: the
+    /// list the preset put it on wins, or always-muted if the preset has both.
+    pub fn apply_to(&self, config: &mut Config, mode: ImportMode) -> ImportReport {
+        let layers = layers::current();
+        let mut report = ImportReport {
+            name: self.name.clone(),
+            mode,
+            added: Vec::new(),
+            removed: Vec::new(),
+            conflicts: Vec::new(),
+            locked: Vec::new(),
+        };
+
+        for key in LISTS {
+            if self.list(key).is_empty() {
+                continue;
+            }
+            if layers.is_locked(key) {
+                report.locked.push(key);
+                continue;
+            }
+
+            let current = config_list_mut(config, key);
+            if mode == ImportMode::Replace {
+                let mut removed: Vec<String> = current
+                    .iter()
+                    .filter(|app| !self.list(key).contains(*app))
+                    .filter(|app| !layers.policy_contains(key, app))
+                    .cloned()
+                    .collect();
+                removed.sort();
+                for app in removed {
+                    current.remove(&app);
+                    report.removed.push((key, app));
+                }
+            }
+            for app in self.list(key) {
+                if current.insert(app.clone()) {
+                    report.added.push((key, app.clone()));
+                }
+            }
+        }
+
+        self.resolve_conflicts(config, &mut report);
+        report
+    }
+
+    fn resolve_conflicts(&self, config: &mut Config, report: &mut ImportReport) {
+        let mut both: Vec<String> = config
+            .excluded_apps
+            .intersection(&config.always_muted_apps)
+            .cloned()
+            .collect();
+        both.sort();
+
+        for app in both {
+            let (kept_in, loser) = if self.excluded_apps.contains(&app)
+                && !self.always_muted_apps.contains(&app)
+            {
+                ("excluded_apps", "always_muted_apps")
+            } else {
+                ("always_muted_apps", "excluded_apps")
+            };
+
+            let removable = !layers::current().is_locked(loser)
+                && !layers::current().policy_contains(loser, &app);
+            if removable {
+                config_list_mut(config, loser).remove(&app);
+                // An entry the import just added and then dropped was never there
+                report.added.retain(|(key, added)| !(*key == loser && *added == app));
+            }
+            report.conflicts.push(Conflict {
+                app,
+                kept_in,
+                removed_from: removable.then_some(loser),
+            });
+        }
+    }
+}
+
+fn config_list_mut<'a>(config: &'a mut Config, key: &str) -> &'a mut HashSet<String> {
+    match key {
+        "excluded_apps" => &mut config.excluded_apps,
+        "always_muted_apps" => &mut config.always_muted_apps,
+        "away_allowed_apps" => &mut config.away_allowed_apps,
+        "focus_transparent_apps" => &mut config.focus_transparent_apps,
+        _ => &mut config.focus_transparent_classes,
+    }
+}
+
+impl ImportReport {
+    /// Whether the import changed anything
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            ImportMode::Merge => "merged",
+            ImportMode::Replace => "replaced",
+        };
+        match &self.name {
+            Some(name) => writeln!(f, "Preset \"{}\" {}.", name, mode)?,
+            None => writeln!(f, "Preset {}.", mode)?,
+        }
+
+        if self.is_empty() {
+            writeln!(f, "Nothing changed: your lists already match the preset.")?;
+        }
+        for (key, app) in &self.added {
+            writeln!(f, "  + {} ({})", app, key)?;
+        }
+        for (key, app) in &self.removed {
+            writeln!(f, "  - {} ({})", app, key)?;
+        }
+
+        if !self.conflicts.is_empty() {
+            writeln!(f, "Conflicts:")?;
+            for conflict in &self.conflicts {
+                match conflict.removed_from {
+                    Some(list) => writeln!(
+                        f,
+                        "  {}: kept in {}, removed from {}",
+                        conflict.app, conflict.kept_in, list
+                    )?,
+                    None => writeln!(
+                        f,
+                        "  {}: on both lists because the policy requires it; {} wins",
+                        conflict.app, conflict.kept_in
+                    )?,
+                }
+            }
+        }
+
+        if !self.locked.is_empty() {
+            writeln!(f, "Locked by policy, not changed: {}", self.locked.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(excluded: &[&str], always_muted: &[&str]) -> Config {
+        Config {
+            excluded_apps: excluded.iter().map(|s| s.to_string()).collect(),
+            always_muted_apps: always_muted.iter().map(|s| s.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let config = config_with(&["spotify.exe", "discord.exe"], &["game.exe"]);
+        let preset = Preset::from_config(&config, Some("Mine".to_string()));
+        let text = serde_json::to_string_pretty(&preset).unwrap();
+
+        // Lists are written sorted so presets diff cleanly
+        assert!(text.find("discord.exe").unwrap() < text.find("spotify.exe").unwrap());
+        assert_eq!(Preset::parse(&text).unwrap(), preset);
+    }
+
+    #[test]
+    fn test_merge_reports_additions_and_conflicts() {
+        let mut config = config_with(&["spotify.exe"], &["obs64.exe"]);
+        let preset = Preset::parse(
+            r#"{"name": "Streaming", "excluded_apps": ["OBS64.exe", "spotify.exe", "vlc.exe"]}"#,
+        )
+        .unwrap();
+
+        let report = preset.apply_to(&mut config, ImportMode::Merge);
+
+        assert!(config.excluded_apps.contains("vlc.exe"));
+        assert!(config.excluded_apps.contains("obs64.exe"));
+        assert!(!config.always_muted_apps.contains("obs64.exe"));
+        let added = |app: &str| ("excluded_apps", app.to_string());
+        assert_eq!(report.added, vec![added("obs64.exe"), added("vlc.exe")]);
+        assert_eq!(
+            report.conflicts,
+            vec![Conflict {
+                app: "obs64.exe".to_string(),
+                kept_in: "excluded_apps",
+                removed_from: Some("always_muted_apps"),
+            }]
+        );
+        assert!(report.to_string().contains("kept in excluded_apps"));
+    }
+
+    #[test]
+    fn test_replace_removes_missing_entries() {
+        let mut config = config_with(&["spotify.exe", "old.exe"], &["game.exe"]);
+        let preset = Preset::parse(r#"{"excluded_apps": ["spotify.exe"]}"#).unwrap();
+
+        let report = preset.apply_to(&mut config, ImportMode::Replace);
+
+        assert_eq!(config.excluded_apps.len(), 1);
+        // Lists the preset doesn't fill are left alone
+        assert!(config.always_muted_apps.contains("game.exe"));
+        assert!(!config.focus_transparent_apps.is_empty());
+        assert_eq!(report.removed, vec![("excluded_apps", "old.exe".to_string())]);
+        assert!(report.added.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_newer_presets() {
+        assert!(Preset::parse(r#"{"preset_version": 99}"#).is_err());
+        assert!(Preset::parse("\"excluded_apps\"").is_err());
+
+        // Blank names are dropped, leaving nothing to import
+        let blank = Preset::parse(r#"{"excluded_apps": [" ", ""]}"#).unwrap();
+        assert!(blank.is_empty());
+    }
+}
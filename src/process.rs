@@ -3,15 +3,20 @@
 
 #![allow(dead_code)]
 
+use std::collections::HashSet;
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
-use windows::Win32::Foundation::{FALSE, CloseHandle};
+use windows::Win32::Foundation::{BOOL, FALSE, CloseHandle, HWND, LPARAM, RECT, TRUE};
+use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED};
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_ALL};
 use windows::Win32::System::ProcessStatus::K32GetModuleFileNameExW;
 use windows::Win32::System::Threading::{
     OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ,
 };
+use windows::Win32::UI::Shell::{IVirtualDesktopManager, VirtualDesktopManager};
 use windows::Win32::UI::WindowsAndMessaging::{
-    GetForegroundWindow, GetWindowThreadProcessId,
+    EnumWindows, GetClassNameW, GetForegroundWindow, GetWindowLongW, GetWindowRect,
+    GetWindowThreadProcessId, IsIconic, IsWindowVisible, GWL_EXSTYLE, WS_EX_TOOLWINDOW,
 };
 
 /// Information about a process
@@ -66,6 +71,208 @@ pub fn get_foreground_pid() -> Option<u32> {
     }
 }
 
+/// The foreground window's owning process and window class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForegroundWindow {
+    pub pid: u32,
+    pub class_name: String,
+}
+
+/// Gets the foreground window's PID and class name
+pub fn get_foreground_window() -> Option<ForegroundWindow> {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.0 == std::ptr::null_mut() {
+            return None;
+        }
+
+        let mut pid: u32 = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        if pid == 0 {
+            return None;
+        }
+
+        let mut buffer = [0u16; 256];
+        let len = GetClassNameW(hwnd, &mut buffer);
+        let class_name = if len > 0 {
+            String::from_utf16_lossy(&buffer[..len as usize])
+        } else {
+            String::new()
+        };
+
+        Some(ForegroundWindow { pid, class_name })
+    }
+}
+
+/// Gets the executable name of a process (e.g., "chrome.exe")
+pub fn get_process_name(pid: u32) -> Option<String> {
+    get_process_info(pid).map(|(name, _)| name)
+}
+
+/// Screen rectangle of a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    /// Checks if this rectangle fully covers another one
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.left <= other.left
+            && self.top <= other.top
+            && self.right >= other.right
+            && self.bottom >= other.bottom
+    }
+
+    /// Checks if the rectangle has no area
+    pub fn is_empty(&self) -> bool {
+        self.right <= self.left || self.bottom <= self.top
+    }
+}
+
+/// A top-level application window, as returned by `enumerate_windows`
+#[derive(Debug, Clone)]
+pub struct TopLevelWindow {
+    pub hwnd: HWND,
+    pub pid: u32,
+    pub rect: Rect,
+    pub minimized: bool,
+    /// Hidden by DWM (suspended UWP apps, windows on other virtual desktops)
+    pub cloaked: bool,
+}
+
+/// Enumerates visible top-level windows in z-order (topmost first)
+/// Tool windows and windows without area are skipped.
+pub fn enumerate_windows() -> Vec<TopLevelWindow> {
+    let mut windows: Vec<TopLevelWindow> = Vec::new();
+    unsafe {
+        let _ = EnumWindows(
+            Some(enum_windows_proc),
+            LPARAM(&mut windows as *mut Vec<TopLevelWindow> as isize),
+        );
+    }
+    windows
+}
+
+unsafe extern "system" fn enum_windows_proc(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let windows = &mut *(lparam.0 as *mut Vec<TopLevelWindow>);
+
+    if !IsWindowVisible(hwnd).as_bool() {
+        return TRUE;
+    }
+
+    let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE) as u32;
+    if ex_style & WS_EX_TOOLWINDOW.0 != 0 {
+        return TRUE;
+    }
+
+    let mut rect = RECT::default();
+    if GetWindowRect(hwnd, &mut rect).is_err() {
+        return TRUE;
+    }
+    let rect = Rect {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    };
+    if rect.is_empty() {
+        return TRUE;
+    }
+
+    let mut pid: u32 = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid));
+    if pid == 0 {
+        return TRUE;
+    }
+
+    let mut cloaked: u32 = 0;
+    let _ = DwmGetWindowAttribute(
+        hwnd,
+        DWMWA_CLOAKED,
+        &mut cloaked as *mut u32 as *mut std::ffi::c_void,
+        std::mem::size_of::<u32>() as u32,
+    );
+
+    windows.push(TopLevelWindow {
+        hwnd,
+        pid,
+        rect,
+        minimized: IsIconic(hwnd).as_bool(),
+        cloaked: cloaked != 0,
+    });
+
+    TRUE
+}
+
+/// Gets the PIDs owning at least one visible, non-minimized, non-occluded window
+pub fn get_visible_window_pids() -> HashSet<u32> {
+    visible_pids(&enumerate_windows())
+}
+
+/// Filters z-ordered windows down to the PIDs that can actually be seen
+///
+/// Occlusion is approximated: a window counts as hidden only when a single
+/// window above it covers it completely (e.g. a maximized window on the same screen).
+fn visible_pids(windows: &[TopLevelWindow]) -> HashSet<u32> {
+    let mut pids = HashSet::new();
+    let mut above: Vec<Rect> = Vec::new();
+
+    for window in windows {
+        if window.minimized || window.cloaked {
+            continue;
+        }
+
+        if !above.iter().any(|r| r.contains(&window.rect)) {
+            pids.insert(window.pid);
+        }
+        above.push(window.rect);
+    }
+
+    pids
+}
+
+/// Gets the PIDs owning at least one window (minimized or not) on the current virtual desktop
+/// Requires COM to be initialized on the calling thread.
+pub fn get_current_desktop_window_pids() -> HashSet<u32> {
+    let windows = enumerate_windows();
+
+    let manager: Option<IVirtualDesktopManager> =
+        match unsafe { CoCreateInstance(&VirtualDesktopManager, None, CLSCTX_ALL) } {
+            Ok(m) => Some(m),
+            Err(e) => {
+                log::warn!("Virtual desktop manager unavailable: {}", e);
+                None
+            }
+        };
+
+    desktop_pids(&windows, |window| match &manager {
+        Some(manager) => unsafe {
+            manager
+                .IsWindowOnCurrentVirtualDesktop(window.hwnd)
+                .map(|b| b.as_bool())
+                .unwrap_or(!window.cloaked)
+        },
+        // Windows on other desktops are cloaked, which is a good enough fallback
+        None => !window.cloaked,
+    })
+}
+
+/// Collects the PIDs of windows that are on the current desktop
+fn desktop_pids(
+    windows: &[TopLevelWindow],
+    is_on_current_desktop: impl Fn(&TopLevelWindow) -> bool,
+) -> HashSet<u32> {
+    windows
+        .iter()
+        .filter(|window| is_on_current_desktop(window))
+        .map(|window| window.pid)
+        .collect()
+}
+
 /// Gets the foreground process info
 pub fn get_foreground_process() -> Option<ProcessInfo> {
     let pid = get_foreground_pid()?;
@@ -159,6 +366,58 @@ mod tests {
         println!("Foreground PID: {:?}", pid);
     }
 
+    #[test]
+    fn test_get_foreground_window() {
+        // May be None in headless environments
+        if let Some(window) = get_foreground_window() {
+            assert_ne!(window.pid, 0);
+        }
+    }
+
+    #[test]
+    fn test_visible_pids_occlusion() {
+        let window = |pid: u32, left: i32, top: i32, right: i32, bottom: i32| TopLevelWindow {
+            hwnd: HWND::default(),
+            pid,
+            rect: Rect { left, top, right, bottom },
+            minimized: false,
+            cloaked: false,
+        };
+
+        let mut minimized = window(4, 0, 0, 800, 600);
+        minimized.minimized = true;
+
+        let windows = vec![
+            window(1, -8, -8, 1928, 1048),   // maximized on the first monitor
+            window(2, 100, 100, 900, 700),   // fully behind the maximized window
+            window(3, 1920, 0, 3840, 1080),  // second monitor
+            minimized,
+        ];
+
+        let pids = visible_pids(&windows);
+        assert!(pids.contains(&1));
+        assert!(!pids.contains(&2));
+        assert!(pids.contains(&3));
+        assert!(!pids.contains(&4));
+    }
+
+    #[test]
+    fn test_desktop_pids() {
+        let window = |pid: u32, cloaked: bool| TopLevelWindow {
+            hwnd: HWND::default(),
+            pid,
+            rect: Rect { left: 0, top: 0, right: 100, bottom: 100 },
+            minimized: pid == 2,
+            cloaked,
+        };
+
+        let windows = vec![window(1, false), window(2, false), window(3, true)];
+        let pids = desktop_pids(&windows, |w| !w.cloaked);
+        assert!(pids.contains(&1));
+        assert!(pids.contains(&2)); // minimized windows still belong to the desktop
+        assert!(!pids.contains(&3));
+    }
+
     #[test]
     fn test_foreground_tracker() {
         let mut tracker = ForegroundTracker::new();
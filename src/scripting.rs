@@ -0,0 +1,360 @@
+//! Policy scripting module
+//! Lets a user-written Rhai script (`policy_script` in the config) decide
+//! sessions before the built-in rules do. Scripts run sandboxed: no file,
+//! network or module access, and bounded operations, call depth and sizes.
+//! A session the script returns "default" for, or fails on, falls back to the
+//! built-in rules. Needs the `scripting` cargo feature; other builds ignore
+//! the setting with a warning.
+
+use crate::muter::Decision;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// What a policy script sees about one audio session
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct ScriptSession {
+    pub pid: u32,
+    pub app: String,
+    pub title: String,
+    pub muted: bool,
+    pub muted_by_us: bool,
+    /// Friendly name of the output device the session plays on
+    pub device: String,
+}
+
+/// What a policy script sees about everything else
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct ScriptContext {
+    pub foreground_pid: Option<u32>,
+    pub foreground_app: Option<String>,
+    pub user_away: bool,
+    pub peek_active: bool,
+    pub unix_time: u64,
+    pub utc_offset_secs: i64,
+}
+
+/// The configured policy script, reloaded when the file changes
+#[derive(Default)]
+pub struct PolicyScript {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    compiled: Option<rhai_policy::Compiled>,
+    /// Last error reported, so a broken script doesn't flood the log
+    last_error: Mutex<Option<String>>,
+}
+
+impl PolicyScript {
+    /// Loads the script at `path`, or drops it if `path` is None
+    ///
+    /// Does nothing if the path and the file's modification time are unchanged.
+    pub fn sync(&mut self, path: Option<PathBuf>) {
+        let modified = path
+            .as_ref()
+            .and_then(|p| std::fs::metadata(p).ok())
+            .and_then(|m| m.modified().ok());
+        if path == self.path && modified == self.modified {
+            return;
+        }
+        self.path = path;
+        self.modified = modified;
+        self.compiled = None;
+        *self.last_error.lock() = None;
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        let compiled = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| rhai_policy::Compiled::new(&source));
+        match compiled {
+            Ok(compiled) => {
+                log::info!("Loaded policy script {:?}", path);
+                self.compiled = Some(compiled);
+            }
+            Err(e) => log::warn!("Policy script {:?} not used: {}", path, e),
+        }
+    }
+
+    /// Whether a script is loaded and will be asked about sessions
+    pub fn is_loaded(&self) -> bool {
+        self.compiled.is_some()
+    }
+
+    /// Asks the script about each session
+    ///
+    /// Sessions left to the built-in rules, including those the script failed
+    /// on, are missing from the result.
+    pub fn decide_all(
+        &self,
+        context: &ScriptContext,
+        sessions: &[ScriptSession],
+    ) -> HashMap<u32, Decision> {
+        let Some(compiled) = &self.compiled else {
+            return HashMap::new();
+        };
+        let mut error = None;
+        let decisions = compiled.decide_all(context, sessions, &mut error);
+
+        // Report an error once, until it changes or goes away
+        let mut last_error = self.last_error.lock();
+        if error != *last_error {
+            match &error {
+                Some(e) => log::warn!("Policy script failed, using the built-in rules: {}", e),
+                None => log::info!("Policy script runs without errors again"),
+            }
+            *last_error = error;
+        }
+        decisions
+    }
+}
+
+#[cfg(feature = "scripting")]
+mod rhai_policy {
+    use super::{ScriptContext, ScriptSession};
+    use crate::muter::Decision;
+    use rhai::{Array, Dynamic, Engine, Map, Scope, AST, INT};
+    use std::collections::HashMap;
+
+    /// Operations one `decide` call may take before it is stopped
+    const MAX_OPERATIONS: u64 = 100_000;
+
+    const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+    /// A script compiled into a locked-down engine
+    pub struct Compiled {
+        engine: Engine,
+        ast: AST,
+    }
+
+    impl Compiled {
+        pub fn new(source: &str) -> Result<Self, String> {
+            let engine = sandboxed_engine();
+            let ast = engine.compile(source).map_err(|e| e.to_string())?;
+            let has_decide = ast
+                .iter_functions()
+                .any(|f| f.name == "decide" && f.params.len() == 2);
+            if !has_decide {
+                return Err("the script has no `fn decide(session, ctx)`".to_string());
+            }
+            Ok(Self { engine, ast })
+        }
+
+        pub fn decide_all(
+            &self,
+            context: &ScriptContext,
+            sessions: &[ScriptSession],
+            error: &mut Option<String>,
+        ) -> HashMap<u32, Decision> {
+            let ctx = context_map(context, sessions);
+            let mut decisions = HashMap::new();
+            for session in sessions {
+                match self.decide(session, &ctx) {
+                    Ok(Some(decision)) => {
+                        decisions.insert(session.pid, decision);
+                    }
+                    Ok(None) => {}
+                    Err(e) => *error = Some(format!("{}: {}", session.app, e)),
+                }
+            }
+            decisions
+        }
+
+        fn decide(
+            &self,
+            session: &ScriptSession,
+            ctx: &Dynamic,
+        ) -> Result<Option<Decision>, String> {
+            let result: Dynamic = self
+                .engine
+                .call_fn(
+                    &mut Scope::new(),
+                    &self.ast,
+                    "decide",
+                    (session_map(session), ctx.clone()),
+                )
+                .map_err(|e| e.to_string())?;
+            if result.is_unit() {
+                return Ok(None);
+            }
+            match result.into_immutable_string() {
+                Ok(value) => parse_decision(&value),
+                Err(type_name) => Err(format!("decide returned a {}, not a string", type_name)),
+            }
+        }
+    }
+
+    /// Parses what `decide` returned
+    fn parse_decision(value: &str) -> Result<Option<Decision>, String> {
+        match value {
+            "mute" => Ok(Some(Decision::ScriptMute)),
+            "unmute" => Ok(Some(Decision::ScriptUnmute)),
+            "allow" => Ok(Some(Decision::ScriptAllow)),
+            "default" => Ok(None),
+            other => Err(format!(
+                "decide returned \"{}\" (expected mute, unmute, allow or default)",
+                other
+            )),
+        }
+    }
+
+    fn sandboxed_engine() -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(4096);
+        engine.set_max_array_size(1024);
+        engine.set_max_map_size(256);
+        engine.disable_symbol("eval");
+        engine.on_print(|text| log::info!("Policy script: {}", text));
+        engine.on_debug(|text, _, _| log::debug!("Policy script: {}", text));
+        engine
+    }
+
+    fn session_map(session: &ScriptSession) -> Dynamic {
+        let mut map = Map::new();
+        map.insert("pid".into(), (session.pid as INT).into());
+        map.insert("app".into(), session.app.to_lowercase().into());
+        map.insert("title".into(), session.title.clone().into());
+        map.insert("muted".into(), session.muted.into());
+        map.insert("muted_by_us".into(), session.muted_by_us.into());
+        map.insert("device".into(), session.device.clone().into());
+        map.into()
+    }
+
+    fn context_map(context: &ScriptContext, sessions: &[ScriptSession]) -> Dynamic {
+        let mut foreground = Map::new();
+        foreground.insert("pid".into(), (context.foreground_pid.unwrap_or(0) as INT).into());
+        let app = context.foreground_app.as_deref().unwrap_or_default();
+        foreground.insert("app".into(), app.to_lowercase().into());
+
+        let local = context.unix_time as i64 + context.utc_offset_secs;
+        let secs_of_day = local.rem_euclid(SECS_PER_DAY);
+        let mut time = Map::new();
+        time.insert("unix".into(), (context.unix_time as INT).into());
+        time.insert("hour".into(), (secs_of_day / 3600).into());
+        time.insert("minute".into(), (secs_of_day % 3600 / 60).into());
+        // 1970-01-01 was a Thursday; 0 is Sunday
+        time.insert("weekday".into(), ((local.div_euclid(SECS_PER_DAY) + 4) % 7).into());
+
+        let mut devices: Vec<&str> = sessions
+            .iter()
+            .map(|s| s.device.as_str())
+            .filter(|d| !d.is_empty())
+            .collect();
+        devices.sort_unstable();
+        devices.dedup();
+        let devices: Array = devices.into_iter().map(|d| d.to_string().into()).collect();
+        let sessions: Array = sessions.iter().map(session_map).collect();
+
+        let mut ctx = Map::new();
+        ctx.insert("foreground".into(), foreground.into());
+        ctx.insert("sessions".into(), sessions.into());
+        ctx.insert("devices".into(), devices.into());
+        ctx.insert("time".into(), time.into());
+        ctx.insert("user_away".into(), context.user_away.into());
+        ctx.insert("peek".into(), context.peek_active.into());
+        ctx.into()
+    }
+}
+
+/// Stand-in for builds without the `scripting` feature; never compiles a script
+#[cfg(not(feature = "scripting"))]
+mod rhai_policy {
+    use super::{ScriptContext, ScriptSession};
+    use crate::muter::Decision;
+    use std::collections::HashMap;
+
+    pub enum Compiled {}
+
+    impl Compiled {
+        pub fn new(_source: &str) -> Result<Self, String> {
+            Err("this build has no scripting support (the `scripting` feature)".to_string())
+        }
+
+        pub fn decide_all(
+            &self,
+            _context: &ScriptContext,
+            _sessions: &[ScriptSession],
+            _error: &mut Option<String>,
+        ) -> HashMap<u32, Decision> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+
+    // 2026-10-18 (a Sunday) 20:30 UTC
+    const EVENING_UTC: u64 = 1_792_355_400;
+
+    fn session(pid: u32, app: &str) -> ScriptSession {
+        ScriptSession {
+            pid,
+            app: app.to_string(),
+            device: "Headphones".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn context(hour_offset: i64) -> ScriptContext {
+        ScriptContext {
+            foreground_pid: Some(1),
+            foreground_app: Some("Game.exe".to_string()),
+            unix_time: EVENING_UTC,
+            utc_offset_secs: hour_offset * 3600,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_script_decides_sessions() {
+        let script = rhai_policy::Compiled::new(
+            r#"
+            fn decide(session, ctx) {
+                if session.app != "chrome.exe" { return "default"; }
+                if ctx.foreground.app == "game.exe" && ctx.time.hour >= 20
+                    && ctx.time.weekday == 0 && ctx.devices == ["Headphones"] {
+                    return "mute";
+                }
+                "allow"
+            }
+            "#,
+        )
+        .unwrap();
+        let sessions = [session(1, "game.exe"), session(2, "Chrome.exe")];
+
+        let mut error = None;
+        let decisions = script.decide_all(&context(0), &sessions, &mut error);
+        assert_eq!(error, None);
+        assert_eq!(decisions.get(&1), None);
+        assert_eq!(decisions.get(&2), Some(&Decision::ScriptMute));
+
+        // 18:30 local time
+        let decisions = script.decide_all(&context(-2), &sessions, &mut error);
+        assert_eq!(decisions.get(&2), Some(&Decision::ScriptAllow));
+    }
+
+    #[test]
+    fn test_script_errors_fall_back() {
+        assert!(rhai_policy::Compiled::new("let x = 1;").is_err());
+        assert!(rhai_policy::Compiled::new("fn decide(session, ctx) { eval(\"1\") }").is_err());
+
+        let endless = rhai_policy::Compiled::new("fn decide(s, ctx) { loop {} }").unwrap();
+        let mut error = None;
+        let decisions = endless.decide_all(&context(0), &[session(2, "a.exe")], &mut error);
+        assert!(decisions.is_empty());
+        assert!(error.is_some());
+
+        let typo = rhai_policy::Compiled::new("fn decide(s, ctx) { \"mutee\" }").unwrap();
+        let mut error = None;
+        assert!(typo.decide_all(&context(0), &[session(2, "a.exe")], &mut error).is_empty());
+        assert!(error.unwrap().contains("mutee"));
+    }
+}
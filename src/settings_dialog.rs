@@ -4,7 +4,12 @@
 //! Uses GDI rendering (CPU-based) - zero GPU/VRAM usage.
 
 use crate::audio::AudioManager;
-use crate::config::Config;
+use crate::clock;
+use crate::config::{self, AudibilityPolicy, Config, ConfigSource};
+use crate::events;
+use crate::layers;
+use crate::muter::MuterEngine;
+use crate::presets::{ImportMode, Preset};
 use crate::startup;
 use parking_lot::RwLock;
 use std::cell::RefCell;
@@ -12,7 +17,8 @@ use std::ffi::{c_void, OsStr};
 use std::os::windows::ffi::OsStrExt;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use windows::core::PCWSTR;
+use std::path::PathBuf;
+use windows::core::{PCWSTR, PWSTR};
 use windows::Win32::Foundation::{HMODULE, HWND, LPARAM, LRESULT, WPARAM};
 use windows::Win32::Graphics::Gdi::{
     CreateFontW, DeleteObject, HBRUSH, HFONT, HGDIOBJ, InvalidateRect,
@@ -21,6 +27,11 @@ use windows::Win32::Graphics::Gdi::{
 };
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows::Win32::UI::Controls::{InitCommonControlsEx, ICC_STANDARD_CLASSES, INITCOMMONCONTROLSEX};
+use windows::Win32::UI::Controls::Dialogs::{
+    GetOpenFileNameW, GetSaveFileNameW, OFN_FILEMUSTEXIST, OFN_OVERWRITEPROMPT,
+    OFN_PATHMUSTEXIST, OPENFILENAMEW,
+};
+use windows::Win32::UI::Input::KeyboardAndMouse::EnableWindow;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
 // Control IDs
@@ -50,6 +61,17 @@ const ID_LABEL_MS: i32 = 122;
 const ID_LABEL_RANGE: i32 = 123;
 const ID_LABEL_CONFIG: i32 = 124;
 const ID_LABEL_PATH: i32 = 125;
+const ID_LABEL_AUDIBILITY: i32 = 127;
+const ID_COMBO_AUDIBILITY: i32 = 128;
+const ID_BTN_EXEMPT: i32 = 129;
+const ID_EDIT_EXEMPT: i32 = 130;
+const ID_LABEL_EXEMPT_HINT: i32 = 131;
+const ID_BTN_EXPLAIN: i32 = 132;
+const ID_BTN_IMPORT_PRESET: i32 = 133;
+const ID_BTN_EXPORT_PRESET: i32 = 134;
+
+/// Suffix marking a temporary exemption in the exclusions list
+const EXEMPT_SUFFIX: &str = " (until ";
 
 // Edit notification
 const EN_CHANGE: u16 = 0x0300;
@@ -60,6 +82,25 @@ const WINDOW_HEIGHT: i32 = 680;
 const MIN_WINDOW_WIDTH: i32 = 820;
 const MIN_WINDOW_HEIGHT: i32 = 600;
 
+// Audibility policy choices, in combo box order
+const AUDIBILITY_CHOICES: [(AudibilityPolicy, &str); 3] = [
+    (AudibilityPolicy::Focus, "Focused app only"),
+    (AudibilityPolicy::Visible, "Visible windows"),
+    (AudibilityPolicy::Workspace, "Current virtual desktop"),
+];
+
+// Controls that edit each config key, read-only while a policy or override locks it
+const LOCKABLE_CONTROLS: [(&str, &[i32]); 8] = [
+    ("muting_enabled", &[ID_CHECK_ENABLED]),
+    ("start_minimized", &[ID_CHECK_START_MINIMIZED]),
+    ("start_with_windows", &[ID_CHECK_START_WINDOWS]),
+    ("poll_interval_ms", &[ID_EDIT_POLL_INTERVAL]),
+    ("audibility_policy", &[ID_COMBO_AUDIBILITY]),
+    ("excluded_apps", &[ID_BTN_ADD_EXCLUSION, ID_BTN_REMOVE_EXCLUSION]),
+    ("always_muted_apps", &[ID_BTN_ADD_ALWAYS_MUTED, ID_BTN_REMOVE_ALWAYS_MUTED]),
+    ("temporary_exemptions", &[ID_BTN_EXEMPT, ID_EDIT_EXEMPT]),
+];
+
 // Button states
 const BST_CHECKED: usize = 1;
 const BST_UNCHECKED: usize = 0;
@@ -74,6 +115,7 @@ struct DialogState {
     config: Arc<RwLock<Config>>,
     muting_enabled: Arc<AtomicBool>,
     audio_manager: Arc<AudioManager>,
+    engine: Arc<RwLock<MuterEngine>>,
     detected_apps: Vec<(u32, String)>, // (pid, name)
     all_detected_apps: Vec<(u32, String)>, // All apps before filtering
     search_filter: String,
@@ -92,6 +134,15 @@ fn to_wide_ptr(s: &str) -> PCWSTR {
     PCWSTR(Box::leak(wide.into_boxed_slice()).as_ptr())
 }
 
+/// Shows a modal message box titled with the app name
+pub fn show_message_box(owner: HWND, text: &str, icon: MESSAGEBOX_STYLE) {
+    let text = to_wide(text);
+    let title = to_wide("Background Muter");
+    unsafe {
+        MessageBoxW(owner, PCWSTR(text.as_ptr()), PCWSTR(title.as_ptr()), MB_OK | icon);
+    }
+}
+
 /// Helper to get dialog item handle, returns default HWND on error
 unsafe fn get_dlg_item(hwnd: HWND, id: i32) -> HWND {
     GetDlgItem(hwnd, id).unwrap_or_default()
@@ -132,6 +183,7 @@ fn init_common_controls() {
 pub fn open_settings_dialog(
     config: Arc<RwLock<Config>>,
     muting_enabled: Arc<AtomicBool>,
+    engine: Arc<RwLock<MuterEngine>>,
 ) {
     // Create audio manager for detecting apps
     let audio_manager = match AudioManager::new() {
@@ -148,6 +200,7 @@ pub fn open_settings_dialog(
             config,
             muting_enabled,
             audio_manager,
+            engine,
             detected_apps: Vec::new(),
             all_detected_apps: Vec::new(),
             search_filter: String::new(),
@@ -393,6 +446,11 @@ unsafe fn create_controls(hwnd: HWND) {
         margin + 10, 252, 100, 26, ID_BTN_REFRESH);
     set_font(btn_refresh, font);
 
+    let btn_explain = create_control(hwnd, hmodule, "BUTTON", "Why?",
+        WS_CHILD | WS_VISIBLE | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+        margin + 115, 252, 70, 26, ID_BTN_EXPLAIN);
+    set_font(btn_explain, font);
+
     // Group box for excluded apps (right side, top)
     let grp_excluded = create_control(
         hwnd,
@@ -484,6 +542,40 @@ unsafe fn create_controls(hwnd: HWND) {
     );
     set_font(btn_add_always, font);
 
+    // Temporary exemption: button + duration/deadline box
+    let btn_exempt = create_control(
+        hwnd,
+        hmodule,
+        "BUTTON",
+        "Let Play For →",
+        WS_CHILD | WS_VISIBLE | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+        margin + 200,
+        buttons_y,
+        120,
+        28,
+        ID_BTN_EXEMPT,
+    );
+    set_font(btn_exempt, font);
+
+    let edit_exempt = create_control(
+        hwnd,
+        hmodule,
+        "EDIT",
+        "1h",
+        WS_CHILD | WS_VISIBLE | WS_BORDER,
+        margin + 325,
+        buttons_y + 2,
+        60,
+        24,
+        ID_EDIT_EXEMPT,
+    );
+    set_font(edit_exempt, font);
+
+    let lbl_exempt_hint = create_control(hwnd, hmodule, "STATIC", "(e.g. 30m, 2h, 18:00)",
+        WS_CHILD | WS_VISIBLE,
+        margin + 200, buttons_y + 38, 185, 18, ID_LABEL_EXEMPT_HINT);
+    set_font(lbl_exempt_hint, font);
+
     let btn_remove = create_control(
         hwnd,
         hmodule,
@@ -546,6 +638,21 @@ unsafe fn create_controls(hwnd: HWND) {
         margin + 13, settings_y + 70, 200, 22, ID_CHECK_START_WINDOWS);
     set_font(chk_startup, font);
 
+    // Audibility policy in the middle column
+    let lbl_audibility = create_control(hwnd, hmodule, "STATIC", "Keep audible:", 
+        WS_CHILD | WS_VISIBLE, 
+        margin + 230, settings_y + 25, 90, 20, ID_LABEL_AUDIBILITY);
+    set_font(lbl_audibility, font);
+
+    let combo_audibility = create_control(hwnd, hmodule, "COMBOBOX", "", 
+        WS_CHILD | WS_VISIBLE | WS_VSCROLL | WINDOW_STYLE(CBS_DROPDOWNLIST as u32), 
+        margin + 325, settings_y + 22, 180, 120, ID_COMBO_AUDIBILITY);
+    set_font(combo_audibility, font);
+    for (_, label) in AUDIBILITY_CHOICES {
+        let wide = to_wide(label);
+        SendMessageW(combo_audibility, CB_ADDSTRING, WPARAM(0), LPARAM(wide.as_ptr() as isize));
+    }
+
     // Poll interval on the right side
     let lbl_poll = create_control(
         hwnd,
@@ -598,12 +705,43 @@ unsafe fn create_controls(hwnd: HWND) {
         margin + 13, settings_y + 98, 70, 18, ID_LABEL_CONFIG);
     set_font(lbl_config, font);
     
-    let config_path = Config::config_path();
-    let path_str = config_path.to_string_lossy();
+    let (config_path, config_source) = Config::config_location();
+    let path_str = match config_source {
+        ConfigSource::Portable => format!("{} (portable)", config_path.display()),
+        _ => config_path.display().to_string(),
+    };
     let lbl_path = create_control(hwnd, hmodule, "STATIC", &path_str, WS_CHILD | WS_VISIBLE, margin + 85, settings_y + 98, width - margin * 2 - 90, 18, ID_LABEL_PATH);
     set_font(lbl_path, font);
 
     // === Bottom Buttons ===
+    let btn_import = create_control(
+        hwnd,
+        hmodule,
+        "BUTTON",
+        "Import Preset...",
+        WS_CHILD | WS_VISIBLE | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+        margin,
+        height - 45,
+        125,
+        32,
+        ID_BTN_IMPORT_PRESET,
+    );
+    set_font(btn_import, font);
+
+    let btn_export = create_control(
+        hwnd,
+        hmodule,
+        "BUTTON",
+        "Export Preset...",
+        WS_CHILD | WS_VISIBLE | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+        margin + 130,
+        height - 45,
+        125,
+        32,
+        ID_BTN_EXPORT_PRESET,
+    );
+    set_font(btn_export, font);
+
     let btn_save_only = create_control(
         hwnd,
         hmodule,
@@ -672,6 +810,7 @@ unsafe fn resize_controls(hwnd: HWND, width: i32, height: i32) {
     move_control(hwnd, ID_EDIT_SEARCH, margin + 65, 26, left_panel_width - 85, 22);
     move_control(hwnd, ID_LIST_DETECTED, margin + 10, 52, left_panel_width - 20, detected_group_height - 85);
     move_control(hwnd, ID_BTN_REFRESH, margin + 10, detected_group_height - 25, 100, 26);
+    move_control(hwnd, ID_BTN_EXPLAIN, margin + 115, detected_group_height - 25, 70, 26);
 
     // Excluded apps group
     move_control(hwnd, ID_GRP_EXCLUDED, right_panel_x, 8, right_panel_width, right_panel_height);
@@ -685,6 +824,9 @@ unsafe fn resize_controls(hwnd: HWND, width: i32, height: i32) {
     let buttons_y = detected_group_height + 15;
     move_control(hwnd, ID_BTN_ADD_EXCLUSION, margin + 10, buttons_y, 180, 28);
     move_control(hwnd, ID_BTN_ADD_ALWAYS_MUTED, margin + 10, buttons_y + 32, 180, 28);
+    move_control(hwnd, ID_BTN_EXEMPT, margin + 200, buttons_y, 120, 28);
+    move_control(hwnd, ID_EDIT_EXEMPT, margin + 325, buttons_y + 2, 60, 24);
+    move_control(hwnd, ID_LABEL_EXEMPT_HINT, margin + 200, buttons_y + 38, 185, 18);
     move_control(hwnd, ID_BTN_REMOVE_EXCLUSION, right_panel_x + right_panel_width - 220, buttons_y, 220, 28);
     move_control(hwnd, ID_BTN_REMOVE_ALWAYS_MUTED, right_panel_x + right_panel_width - 240, buttons_y + 32, 240, 28);
 
@@ -694,6 +836,8 @@ unsafe fn resize_controls(hwnd: HWND, width: i32, height: i32) {
     move_control(hwnd, ID_CHECK_ENABLED, margin + 13, settings_y + 22, 200, 22);
     move_control(hwnd, ID_CHECK_START_MINIMIZED, margin + 13, settings_y + 46, 200, 22);
     move_control(hwnd, ID_CHECK_START_WINDOWS, margin + 13, settings_y + 70, 200, 22);
+    move_control(hwnd, ID_LABEL_AUDIBILITY, margin + 230, settings_y + 25, 90, 20);
+    move_control(hwnd, ID_COMBO_AUDIBILITY, margin + 325, settings_y + 22, 180, 120);
     move_control(hwnd, ID_LABEL_POLL, width - 260, settings_y + 25, 90, 20);
     move_control(hwnd, ID_EDIT_POLL_INTERVAL, width - 165, settings_y + 22, 60, 24);
     move_control(hwnd, ID_LABEL_MS, width - 100, settings_y + 25, 25, 20);
@@ -702,6 +846,8 @@ unsafe fn resize_controls(hwnd: HWND, width: i32, height: i32) {
     move_control(hwnd, ID_LABEL_PATH, margin + 85, settings_y + 98, width - margin * 2 - 90, 18);
 
     // Bottom buttons
+    move_control(hwnd, ID_BTN_IMPORT_PRESET, margin, height - 45, 125, 32);
+    move_control(hwnd, ID_BTN_EXPORT_PRESET, margin + 130, height - 45, 125, 32);
     move_control(hwnd, ID_BTN_SAVE_ONLY, width - 330, height - 45, 95, 32);
     move_control(hwnd, ID_BTN_SAVE_CLOSE, width - 225, height - 45, 105, 32);
     move_control(hwnd, ID_BTN_CLOSE, width - 112, height - 45, 100, 32);
@@ -859,6 +1005,35 @@ unsafe fn refresh_excluded_list(hwnd: HWND) {
                     LPARAM(wide.as_ptr() as isize),
                 );
             }
+
+            // Temporary exemptions follow, annotated with their expiry
+            let now = config::unix_now();
+            let offset = clock::local_utc_offset_secs();
+            let mut exemptions: Vec<_> = s
+                .config
+                .read()
+                .temporary_exemptions
+                .iter()
+                .filter(|(_, until)| **until > now)
+                .map(|(app, until)| (app.clone(), *until))
+                .collect();
+            exemptions.sort();
+
+            for (app, until) in exemptions {
+                let label = format!(
+                    "{}{}{})",
+                    app,
+                    EXEMPT_SUFFIX,
+                    clock::format_local_deadline(until, now, offset)
+                );
+                let wide = to_wide(&label);
+                SendMessageW(
+                    list_excluded,
+                    LB_ADDSTRING,
+                    WPARAM(0),
+                    LPARAM(wide.as_ptr() as isize),
+                );
+            }
         }
     });
 }
@@ -919,9 +1094,31 @@ unsafe fn load_settings_to_controls(hwnd: HWND) {
                 LPARAM(0),
             );
 
+            // Audibility policy combo box
+            let audibility_index = AUDIBILITY_CHOICES
+                .iter()
+                .position(|(policy, _)| *policy == config.audibility_policy)
+                .unwrap_or(0);
+            SendMessageW(
+                get_dlg_item(hwnd, ID_COMBO_AUDIBILITY),
+                CB_SETCURSEL,
+                WPARAM(audibility_index),
+                LPARAM(0),
+            );
+
             // Poll interval edit
             let poll_str = to_wide(&config.poll_interval_ms.to_string());
             let _ = SetWindowTextW(get_dlg_item(hwnd, ID_EDIT_POLL_INTERVAL), PCWSTR(poll_str.as_ptr()));
+
+            // Locked settings are shown but can't be edited
+            let layers = layers::current();
+            for (key, ids) in LOCKABLE_CONTROLS {
+                if layers.is_locked(key) {
+                    for id in ids {
+                        let _ = EnableWindow(get_dlg_item(hwnd, *id), false);
+                    }
+                }
+            }
         }
     });
 }
@@ -961,12 +1158,24 @@ unsafe fn handle_command(hwnd: HWND, control_id: i32, notification: u16) {
         ID_BTN_REMOVE_EXCLUSION => {
             remove_selected_exclusion(hwnd);
         }
+        ID_BTN_EXEMPT => {
+            exempt_selected_app(hwnd);
+        }
+        ID_BTN_EXPLAIN => {
+            explain_selected_app(hwnd);
+        }
         ID_BTN_ADD_ALWAYS_MUTED => {
             add_selected_to_always_muted(hwnd);
         }
         ID_BTN_REMOVE_ALWAYS_MUTED => {
             remove_selected_always_muted(hwnd);
         }
+        ID_BTN_IMPORT_PRESET => {
+            import_preset(hwnd);
+        }
+        ID_BTN_EXPORT_PRESET => {
+            export_preset(hwnd);
+        }
         ID_BTN_SAVE_ONLY => {
             save_settings(hwnd);
         }
@@ -1025,10 +1234,74 @@ unsafe fn remove_selected_exclusion(hwnd: HWND) {
 
     let app_name = String::from_utf16_lossy(&buffer[..text_len]);
 
+    let is_exemption = app_name.contains(EXEMPT_SUFFIX);
+    if !is_exemption && !Config::is_list_entry_removable("excluded_apps", &app_name) {
+        show_policy_entry_message(hwnd, &app_name);
+        return;
+    }
+
     DIALOG_STATE.with(|state| {
         if let Some(ref s) = *state.borrow() {
             let mut config = s.config.write();
-            config.remove_excluded_app(&app_name);
+            match app_name.split_once(EXEMPT_SUFFIX) {
+                Some((app, _)) => config.remove_temporary_exemption(app),
+                None => config.remove_excluded_app(&app_name),
+            }
+        }
+    });
+
+    refresh_detected_apps(hwnd);
+}
+
+unsafe fn explain_selected_app(hwnd: HWND) {
+    let list_detected = get_dlg_item(hwnd, ID_LIST_DETECTED);
+    let sel_idx = SendMessageW(list_detected, LB_GETCURSEL, WPARAM(0), LPARAM(0)).0 as i32;
+
+    if sel_idx < 0 {
+        show_message_box(hwnd, "Select an app in Detected Audio Apps first.", MB_ICONINFORMATION);
+        return;
+    }
+
+    let explanation = DIALOG_STATE.with(|state| {
+        state.borrow().as_ref().and_then(|s| {
+            let (pid, _) = s.detected_apps.get(sel_idx as usize)?;
+            Some(s.engine.read().explain(*pid))
+        })
+    });
+
+    if let Some(explanation) = explanation {
+        show_message_box(hwnd, &explanation.to_string(), MB_ICONINFORMATION);
+    }
+}
+
+unsafe fn exempt_selected_app(hwnd: HWND) {
+    let list_detected = get_dlg_item(hwnd, ID_LIST_DETECTED);
+    let sel_idx = SendMessageW(list_detected, LB_GETCURSEL, WPARAM(0), LPARAM(0)).0 as i32;
+
+    if sel_idx < 0 {
+        return; // Nothing selected
+    }
+
+    let mut buffer = [0u16; 32];
+    GetWindowTextW(get_dlg_item(hwnd, ID_EDIT_EXEMPT), &mut buffer);
+    let text = String::from_utf16_lossy(&buffer);
+    let text = text.trim_matches('\0');
+
+    let until = clock::parse_deadline(text, config::unix_now(), clock::local_utc_offset_secs());
+    let Some(until) = until else {
+        show_message_box(
+            hwnd,
+            &format!("'{}' is not a duration (30m, 2h) or time of day (18:00).", text),
+            MB_ICONWARNING,
+        );
+        return;
+    };
+
+    DIALOG_STATE.with(|state| {
+        if let Some(ref s) = *state.borrow() {
+            if let Some((_, name)) = s.detected_apps.get(sel_idx as usize) {
+                s.config.write().add_temporary_exemption(name, until);
+            }
         }
     });
 
@@ -1079,6 +1352,11 @@ unsafe fn remove_selected_always_muted(hwnd: HWND) {
 
     let app_name = String::from_utf16_lossy(&buffer[..text_len]);
 
+    if !Config::is_list_entry_removable("always_muted_apps", &app_name) {
+        show_policy_entry_message(hwnd, &app_name);
+        return;
+    }
+
     DIALOG_STATE.with(|state| {
         if let Some(ref s) = *state.borrow() {
             let mut config = s.config.write();
@@ -1089,6 +1367,126 @@ unsafe fn remove_selected_always_muted(hwnd: HWND) {
     refresh_detected_apps(hwnd);
 }
 
+/// Asks for a preset file with the Open dialog, or Save As when `save`
+unsafe fn choose_preset_file(owner: HWND, save: bool) -> Option<PathBuf> {
+    // to_wide adds the second NUL that ends the filter list
+    let filter = to_wide("Presets (*.json)\0*.json\0All files (*.*)\0*.*\0");
+    let default_ext = to_wide("json");
+    let mut file = vec![0u16; 1024];
+    if save {
+        let name = to_wide("bg-muter-preset.json");
+        file[..name.len()].copy_from_slice(&name);
+    }
+
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+        hwndOwner: owner,
+        lpstrFilter: PCWSTR(filter.as_ptr()),
+        lpstrFile: PWSTR(file.as_mut_ptr()),
+        nMaxFile: file.len() as u32,
+        lpstrDefExt: PCWSTR(default_ext.as_ptr()),
+        Flags: if save {
+            OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST
+        } else {
+            OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST
+        },
+        ..Default::default()
+    };
+
+    let chosen = if save {
+        GetSaveFileNameW(&mut ofn)
+    } else {
+        GetOpenFileNameW(&mut ofn)
+    };
+    if !chosen.as_bool() {
+        return None;
+    }
+    let len = file.iter().position(|c| *c == 0).unwrap_or(file.len());
+    Some(PathBuf::from(String::from_utf16_lossy(&file[..len])))
+}
+
+unsafe fn export_preset(hwnd: HWND) {
+    let Some(config) = DIALOG_STATE.with(|state| state.borrow().as_ref().map(|s| s.config.clone()))
+    else {
+        return;
+    };
+    let Some(path) = choose_preset_file(hwnd, true) else {
+        return;
+    };
+
+    let preset = Preset::from_config(&config.read(), None);
+    match preset.save(&path) {
+        Ok(()) => show_message_box(
+            hwnd,
+            &format!("Exported {} entries to {}", preset.len(), path.display()),
+            MB_ICONINFORMATION,
+        ),
+        Err(e) => show_message_box(hwnd, &format!("Export failed: {}", e), MB_ICONWARNING),
+    }
+}
+
+unsafe fn import_preset(hwnd: HWND) {
+    let Some((config, engine)) = DIALOG_STATE.with(|state| {
+        state.borrow().as_ref().map(|s| (s.config.clone(), s.engine.clone()))
+    }) else {
+        return;
+    };
+    let Some(path) = choose_preset_file(hwnd, false) else {
+        return;
+    };
+
+    let preset = match Preset::load(&path) {
+        Ok(preset) => preset,
+        Err(e) => {
+            show_message_box(hwnd, &format!("Can't read {}: {}", path.display(), e), MB_ICONWARNING);
+            return;
+        }
+    };
+    if preset.is_empty() {
+        let message = format!("{} has no apps to import", path.display());
+        show_message_box(hwnd, &message, MB_ICONWARNING);
+        return;
+    }
+
+    let question = format!(
+        "Import {} ({} entries)?\n\n\
+         Yes: add them to your lists\n\
+         No: replace the lists the preset contains\n\
+         Cancel: don't import",
+        preset.name.as_deref().unwrap_or("this preset"),
+        preset.len()
+    );
+    let text = to_wide(&question);
+    let title = to_wide("Background Muter");
+    let mode = match MessageBoxW(
+        hwnd,
+        PCWSTR(text.as_ptr()),
+        PCWSTR(title.as_ptr()),
+        MB_YESNOCANCEL | MB_ICONQUESTION,
+    ) {
+        IDYES => ImportMode::Merge,
+        IDNO => ImportMode::Replace,
+        _ => return,
+    };
+
+    let report = {
+        let mut config = config.write();
+        let report = preset.apply_to(&mut config, mode);
+        let _ = config.save();
+        report
+    };
+    engine.write().request_refresh();
+    refresh_detected_apps(hwnd);
+
+    let icon = if report.conflicts.is_empty() { MB_ICONINFORMATION } else { MB_ICONWARNING };
+    show_message_box(hwnd, &report.to_string(), icon);
+}
+
+fn show_policy_entry_message(owner: HWND, app_name: &str) {
+    let text = format!("{} is required by your team policy and can't be removed.", app_name);
+    show_message_box(owner, &text, MB_ICONINFORMATION);
+}
+
 unsafe fn save_settings(hwnd: HWND) {
     DIALOG_STATE.with(|state| {
         if let Some(ref s) = *state.borrow() {
@@ -1116,6 +1514,13 @@ unsafe fn save_settings(hwnd: HWND) {
                 LPARAM(0),
             ).0 == BST_CHECKED as isize;
 
+            let audibility_index = SendMessageW(
+                get_dlg_item(hwnd, ID_COMBO_AUDIBILITY),
+                CB_GETCURSEL,
+                WPARAM(0),
+                LPARAM(0),
+            ).0;
+
             // Read poll interval
             let mut buffer: [u16; 32] = [0; 32];
             GetWindowTextW(get_dlg_item(hwnd, ID_EDIT_POLL_INTERVAL), &mut buffer);
@@ -1127,6 +1532,12 @@ unsafe fn save_settings(hwnd: HWND) {
             config.muting_enabled = muting_checked;
             config.start_minimized = start_minimized_checked;
             config.poll_interval_ms = poll_interval;
+            if let Some((policy, _)) = usize::try_from(audibility_index)
+                .ok()
+                .and_then(|i| AUDIBILITY_CHOICES.get(i))
+            {
+                config.audibility_policy = *policy;
+            }
 
             // Handle startup setting change
             if config.start_with_windows != start_windows_checked {
@@ -1137,10 +1548,10 @@ unsafe fn save_settings(hwnd: HWND) {
             }
 
             // Update atomic muting state
-            s.muting_enabled.store(muting_checked, Ordering::SeqCst);
+            events::store_muting_flag(&s.muting_enabled, muting_checked);
 
-            // Save to disk
-            let _ = config.save();
+            // Save to disk (an explicit save also replaces a config file that failed to load)
+            let _ = config.save_explicitly();
         }
     });
 }
@@ -3,9 +3,9 @@
 //! Uses HKCU\\Software\\Microsoft\\Windows\\CurrentVersion\\Run
 //! so no admin elevation is required.
 
+use crate::config::{Config, ConfigSource};
+use crate::wide::to_wide_null;
 use std::error::Error;
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
 
 use windows::core::PCWSTR;
 use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, WIN32_ERROR};
@@ -17,10 +17,6 @@ use windows::Win32::System::Registry::{
 const RUN_SUBKEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
 const VALUE_NAME: &str = "Background Muter";
 
-fn to_wide_null(s: &str) -> Vec<u16> {
-    OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect()
-}
-
 fn open_hkcu_run_key_set_value() -> Result<HKEY, Box<dyn Error>> {
     let mut key = HKEY::default();
     let subkey = to_wide_null(RUN_SUBKEY);
@@ -48,7 +44,12 @@ pub fn set_run_at_startup(enabled: bool) -> Result<(), Box<dyn Error>> {
         let exe = std::env::current_exe()?;
         let exe = exe.to_string_lossy();
         // Quote to survive spaces in the path.
-        let command = format!("\"{}\"", exe);
+        let mut command = format!("\"{}\"", exe);
+        // Logins don't see --config or a per-session environment variable
+        let (config_path, source) = Config::config_location();
+        if matches!(source, ConfigSource::CommandLine | ConfigSource::Environment) {
+            command.push_str(&format!(" --config \"{}\"", config_path.display()));
+        }
         let command_w = to_wide_null(&command);
 
         // REG_SZ expects bytes including the NUL terminator.
@@ -7,29 +7,66 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use crossbeam_channel::{unbounded, Receiver};
+use crossbeam_channel::{unbounded, Receiver, Sender};
 use tray_icon::{
-    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu},
     Icon, MouseButton, TrayIcon, TrayIconBuilder, TrayIconEvent,
 };
 
+use crate::hotkeys::{HotkeyAction, HotkeyManager};
+
 /// Embedded icon bytes
 const ICON_BYTES: &[u8] = include_bytes!("../assets/icon.png");
 
+/// Exemption presets offered in the tray menu
+const EXEMPT_PRESETS: [(&str, Duration); 2] = [
+    ("For 1 hour", Duration::from_secs(60 * 60)),
+    ("For 4 hours", Duration::from_secs(4 * 60 * 60)),
+];
+
+/// Snooze presets offered in the tray menu
+const SNOOZE_PRESETS: [(&str, Duration); 4] = [
+    ("15 minutes", Duration::from_secs(15 * 60)),
+    ("30 minutes", Duration::from_secs(30 * 60)),
+    ("1 hour", Duration::from_secs(60 * 60)),
+    ("2 hours", Duration::from_secs(2 * 60 * 60)),
+];
+
 /// Commands from tray interactions
 #[derive(Debug, Clone, Copy)]
 pub enum TrayCommand {
     ToggleMuting,
     OpenSettings,
     Exit,
+    /// Keep everything audible for a while, then resume muting
+    Snooze(Duration),
+    /// End an active snooze early
+    CancelSnooze,
+    /// Exempt the foreground app from muting for a while
+    ExemptCurrent(Duration),
+    /// Exempt the foreground app from muting until local midnight
+    ExemptCurrentForToday,
+    /// Show the most recent mute/unmute decisions
+    ShowRecentActivity,
+    /// Turn dry-run (decide and log, but never touch audio) on/off
+    ToggleDryRun,
+    /// config.json changed on disk
+    ReloadConfig,
+    /// A global hotkey was pressed
+    Hotkey(HotkeyAction),
 }
 
 /// Lightweight system tray manager
 pub struct SystemTray {
     _tray_icon: TrayIcon,
     menu_toggle: MenuItem,
+    menu_resume: MenuItem,
+    menu_dry_run: CheckMenuItem,
+    command_tx: Sender<TrayCommand>,
     command_rx: Receiver<TrayCommand>,
     last_muting_state: bool,
+    last_tooltip: String,
+    dry_run: bool,
     exit_flag: Arc<AtomicBool>,
 }
 
@@ -46,12 +83,38 @@ impl SystemTray {
         };
 
         let menu_toggle = MenuItem::new(toggle_text, true, None);
+        let menu_snooze = Submenu::new("Snooze", true);
+        let snooze_items: Vec<(MenuItem, Duration)> = SNOOZE_PRESETS
+            .iter()
+            .map(|(label, duration)| (MenuItem::new(*label, true, None), *duration))
+            .collect();
+        for (item, _) in &snooze_items {
+            menu_snooze.append(item)?;
+        }
+        let menu_resume = MenuItem::new("Resume Muting", false, None);
+        let menu_exempt = Submenu::new("Let Current App Play", true);
+        let exempt_items: Vec<(MenuItem, Duration)> = EXEMPT_PRESETS
+            .iter()
+            .map(|(label, duration)| (MenuItem::new(*label, true, None), *duration))
+            .collect();
+        for (item, _) in &exempt_items {
+            menu_exempt.append(item)?;
+        }
+        let menu_exempt_today = MenuItem::new("Until End of Day", true, None);
+        menu_exempt.append(&menu_exempt_today)?;
+        let menu_dry_run = CheckMenuItem::new("Dry Run (Simulate Only)", true, false, None);
+        let menu_activity = MenuItem::new("Recent Activity...", true, None);
         let menu_settings = MenuItem::new("Settings...", true, None);
         let menu_separator = PredefinedMenuItem::separator();
         let menu_exit = MenuItem::new("Exit", true, None);
 
         let menu = Menu::new();
         menu.append(&menu_toggle)?;
+        menu.append(&menu_snooze)?;
+        menu.append(&menu_resume)?;
+        menu.append(&menu_exempt)?;
+        menu.append(&menu_dry_run)?;
+        menu.append(&menu_activity)?;
         menu.append(&menu_settings)?;
         menu.append(&menu_separator)?;
         menu.append(&menu_exit)?;
@@ -60,6 +123,18 @@ impl SystemTray {
         let (command_tx, command_rx) = unbounded();
 
         let toggle_id = menu_toggle.id().clone();
+        let snooze_ids: Vec<_> = snooze_items
+            .iter()
+            .map(|(item, duration)| (item.id().clone(), *duration))
+            .collect();
+        let resume_id = menu_resume.id().clone();
+        let exempt_ids: Vec<_> = exempt_items
+            .iter()
+            .map(|(item, duration)| (item.id().clone(), *duration))
+            .collect();
+        let exempt_today_id = menu_exempt_today.id().clone();
+        let dry_run_id = menu_dry_run.id().clone();
+        let activity_id = menu_activity.id().clone();
         let settings_id = menu_settings.id().clone();
         let exit_id = menu_exit.id().clone();
 
@@ -71,6 +146,20 @@ impl SystemTray {
         MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
             let cmd = if event.id == toggle_id {
                 Some(TrayCommand::ToggleMuting)
+            } else if let Some((_, duration)) = snooze_ids.iter().find(|(id, _)| event.id == *id) {
+                Some(TrayCommand::Snooze(*duration))
+            } else if event.id == resume_id {
+                Some(TrayCommand::CancelSnooze)
+            } else if let Some((_, duration)) =
+                exempt_ids.iter().find(|(id, _)| event.id == *id)
+            {
+                Some(TrayCommand::ExemptCurrent(*duration))
+            } else if event.id == exempt_today_id {
+                Some(TrayCommand::ExemptCurrentForToday)
+            } else if event.id == dry_run_id {
+                Some(TrayCommand::ToggleDryRun)
+            } else if event.id == activity_id {
+                Some(TrayCommand::ShowRecentActivity)
             } else if event.id == settings_id {
                 Some(TrayCommand::OpenSettings)
             } else if event.id == exit_id {
@@ -97,15 +186,11 @@ impl SystemTray {
         }));
 
         // Build tray icon
-        let tooltip = if muting_enabled {
-            "Background Muter - Active"
-        } else {
-            "Background Muter - Disabled"
-        };
+        let tooltip = tooltip_text(muting_enabled, None, false);
 
         // Do NOT show menu on left click - only right click shows context menu
         let tray_icon = TrayIconBuilder::new()
-            .with_tooltip(tooltip)
+            .with_tooltip(&tooltip)
             .with_icon(icon)
             .with_menu(Box::new(menu))
             .with_menu_on_left_click(false)  // Only right-click shows menu
@@ -114,32 +199,50 @@ impl SystemTray {
         Ok(Self {
             _tray_icon: tray_icon,
             menu_toggle,
+            menu_resume,
+            menu_dry_run,
+            command_tx,
             command_rx,
             last_muting_state: muting_enabled,
+            last_tooltip: tooltip,
+            dry_run: false,
             exit_flag,
         })
     }
 
     /// Updates the tray state (tooltip and menu text)
-    pub fn update_state(&mut self, muting_enabled: bool) {
-        if self.last_muting_state == muting_enabled {
-            return;
+    /// `snooze_remaining` is the time left on an active snooze, if any.
+    pub fn update_state(&mut self, muting_enabled: bool, snooze_remaining: Option<Duration>) {
+        if self.last_muting_state != muting_enabled {
+            self.last_muting_state = muting_enabled;
+
+            let toggle_text = if muting_enabled {
+                "Disable Muting"
+            } else {
+                "Enable Muting"
+            };
+            let _ = self.menu_toggle.set_text(toggle_text);
         }
-        self.last_muting_state = muting_enabled;
 
-        let toggle_text = if muting_enabled {
-            "Disable Muting"
-        } else {
-            "Enable Muting"
-        };
-        let _ = self.menu_toggle.set_text(toggle_text);
+        let tooltip = tooltip_text(muting_enabled, snooze_remaining, self.dry_run);
+        if self.last_tooltip != tooltip {
+            self.menu_resume.set_enabled(snooze_remaining.is_some());
+            let _ = self._tray_icon.set_tooltip(Some(&tooltip));
+            self.last_tooltip = tooltip;
+        }
+    }
 
-        let tooltip = if muting_enabled {
-            "Background Muter - Active"
-        } else {
-            "Background Muter - Disabled"
-        };
-        let _ = self._tray_icon.set_tooltip(Some(tooltip));
+    /// Shows whether dry-run is on (the tooltip follows on the next update_state)
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        if self.dry_run != dry_run {
+            self.dry_run = dry_run;
+            self.menu_dry_run.set_checked(dry_run);
+        }
+    }
+
+    /// Gets a sender for posting commands from other threads
+    pub fn command_sender(&self) -> Sender<TrayCommand> {
+        self.command_tx.clone()
     }
 
     /// Polls for a command (non-blocking)
@@ -173,6 +276,12 @@ impl SystemTray {
             // Process all pending messages
             let mut msg = std::mem::zeroed();
             while PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE).as_bool() {
+                // Hotkeys arrive as thread messages with no window to dispatch to
+                if let Some(action) = HotkeyManager::action_for_message(&msg) {
+                    let _ = self.command_tx.try_send(TrayCommand::Hotkey(action));
+                    continue;
+                }
+
                 let _ = TranslateMessage(&msg);
                 DispatchMessageW(&msg);
             }
@@ -190,6 +299,34 @@ impl Drop for SystemTray {
     }
 }
 
+/// Builds the tooltip for the current state
+fn tooltip_text(muting_enabled: bool, snooze_remaining: Option<Duration>, dry_run: bool) -> String {
+    let tooltip = match snooze_remaining {
+        _ if !muting_enabled => "Background Muter - Disabled".to_string(),
+        Some(remaining) => format!(
+            "Background Muter - Snoozed ({} left)",
+            format_remaining(remaining)
+        ),
+        None => "Background Muter - Active".to_string(),
+    };
+
+    if dry_run && muting_enabled {
+        tooltip + " [Dry Run]"
+    } else {
+        tooltip
+    }
+}
+
+/// Formats a remaining duration as "5 min" or "1 h 05 min" (rounded up to the minute)
+fn format_remaining(remaining: Duration) -> String {
+    let minutes = remaining.as_secs().div_ceil(60);
+    if minutes < 60 {
+        format!("{} min", minutes)
+    } else {
+        format!("{} h {:02} min", minutes / 60, minutes % 60)
+    }
+}
+
 /// Loads the tray icon from embedded PNG
 fn load_tray_icon() -> Result<Icon, Box<dyn std::error::Error>> {
     let img = image::load_from_memory(ICON_BYTES)?
@@ -199,3 +336,30 @@ fn load_tray_icon() -> Result<Icon, Box<dyn std::error::Error>> {
     let rgba = img.into_raw();
     Ok(Icon::from_rgba(rgba, width, height)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(format_remaining(Duration::from_secs(30)), "1 min");
+        assert_eq!(format_remaining(Duration::from_secs(15 * 60)), "15 min");
+        assert_eq!(format_remaining(Duration::from_secs(65 * 60 - 10)), "1 h 05 min");
+    }
+
+    #[test]
+    fn test_tooltip_text() {
+        assert_eq!(tooltip_text(true, None, false), "Background Muter - Active");
+        assert_eq!(
+            tooltip_text(false, Some(Duration::from_secs(60)), false),
+            "Background Muter - Disabled"
+        );
+        assert_eq!(
+            tooltip_text(true, Some(Duration::from_secs(600)), false),
+            "Background Muter - Snoozed (10 min left)"
+        );
+        assert_eq!(tooltip_text(true, None, true), "Background Muter - Active [Dry Run]");
+        assert_eq!(tooltip_text(false, None, true), "Background Muter - Disabled");
+    }
+}
@@ -0,0 +1,7 @@
+//! Wide string helper
+//! Converts Rust strings for the Win32 `W` APIs.
+
+/// Encodes `s` as a null-terminated UTF-16 string
+pub(crate) fn to_wide_null(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}